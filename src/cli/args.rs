@@ -0,0 +1,156 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use crate::core::Board;
+use crate::logging::LogFormat;
+
+#[derive(Parser)]
+#[command(name = "side_scroller", version, about = "A two-lane terminal side scroller")]
+pub struct Args {
+    /// JSON config file with server and logging settings
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Log filter, e.g. "debug" or "side_scroller=debug,warp=info"
+    #[arg(long, global = true, value_name = "FILTER")]
+    pub log_level: Option<String>,
+
+    /// Log output format: text or json
+    #[arg(long, global = true, value_name = "FORMAT")]
+    pub log_format: Option<LogFormat>,
+
+    /// Write logs to this file (interactive modes default to side_scroller.log)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Keep all data in a log file at this path instead of the default store
+    #[arg(long, global = true, value_name = "PATH")]
+    pub store_path: Option<PathBuf>,
+
+    /// Keep all data in a SQLite database at this path
+    #[cfg(feature = "sqlite")]
+    #[arg(long, global = true, value_name = "PATH", conflicts_with_all = ["store_path", "in_memory"])]
+    pub sqlite: Option<PathBuf>,
+
+    /// Keep all data in memory; nothing is saved when the program exits
+    #[arg(long, global = true, conflicts_with = "store_path")]
+    pub in_memory: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Play in the terminal (the default when no command is given)
+    Play(PlayArgs),
+    /// Run the HTTP game server
+    Serve(ServeArgs),
+    /// Open the key-value store admin shell, or run admin commands non-interactively
+    Db(DbArgs),
+    /// Play on a remote game server
+    Connect {
+        /// Server base URL, e.g. http://localhost:3000
+        url: String,
+        /// Reuse an existing player token instead of registering
+        #[arg(long)]
+        token: Option<String>,
+        /// Open a race room and start it once the other racers have joined
+        #[arg(long)]
+        race: bool,
+        /// Join another player's race room
+        #[arg(long, value_name = "ROOM_ID", conflicts_with = "race")]
+        room: Option<String>,
+    },
+    /// Watch a live game on a remote server
+    Watch {
+        /// Server base URL, e.g. http://localhost:3000
+        url: String,
+        game_id: String,
+    },
+    /// Play back a recorded local game
+    Replay {
+        /// Replay id; defaults to the most recent game
+        id: Option<String>,
+        /// List recorded games instead of playing one back
+        #[arg(long, conflicts_with = "id")]
+        list: bool,
+    },
+    /// Show a player's lifetime statistics
+    Stats {
+        name: String,
+    },
+    /// Show the leaderboard, or one player's score history
+    Scores {
+        /// Show every recorded score for this player
+        #[arg(long, value_name = "NAME")]
+        player: Option<String>,
+        /// Leaderboard to show: all-time, daily, weekly or rolling
+        #[arg(long, conflicts_with = "player")]
+        board: Option<Board>,
+        /// Show today's challenge board
+        #[arg(long, conflicts_with_all = ["player", "board"])]
+        challenge: bool,
+    },
+}
+
+#[derive(ClapArgs)]
+#[command(after_help = "Exit codes: 0 success, 1 store or I/O failure, 2 usage error or unknown command, \
+3 key not found, 4 invalid value")]
+pub struct DbArgs {
+    /// Print one JSON object per command instead of text
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub command: Option<DbCommand>,
+}
+
+#[derive(Subcommand)]
+pub enum DbCommand {
+    /// Run a single command, e.g. db exec "get hiscore_ttl"
+    Exec {
+        command: String,
+    },
+    /// Run commands from a script file, one per line ('#' starts a comment)
+    Run {
+        script: PathBuf,
+    },
+}
+
+#[derive(ClapArgs, Default)]
+pub struct PlayArgs {
+    /// Use a fixed obstacle course seed
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Play today's challenge: the same course for everyone, one attempt per player per UTC day
+    #[arg(long, conflicts_with = "seed")]
+    pub daily: bool,
+}
+
+#[derive(ClapArgs)]
+pub struct ServeArgs {
+    /// Port to listen on [default: 3000]
+    #[arg(long, short)]
+    pub port: Option<u16>,
+
+    /// Address to bind to [default: 127.0.0.1]
+    #[arg(long)]
+    pub bind: Option<IpAddr>,
+
+    /// Players and games a client may create per minute [default: 10]
+    #[arg(long, value_name = "N")]
+    pub new_games_per_minute: Option<u32>,
+
+    /// Moves a client may send per second [default: 20]
+    #[arg(long, value_name = "N")]
+    pub moves_per_second: Option<u32>,
+
+    /// Largest accepted request body in bytes [default: 1024]
+    #[arg(long, value_name = "BYTES")]
+    pub max_body_bytes: Option<u64>,
+
+    /// Seconds between session snapshots [default: 5]
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub snapshot_interval: Option<u64>,
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::storage::{self, Storage};
+
+// One line of an export file. `ttl` is the number of seconds the key had
+// left when it was exported, or None for keys that never expire.
+#[derive(Serialize, Deserialize)]
+pub struct Record {
+    pub key: String,
+    pub value: Value,
+    #[serde(default)]
+    pub ttl: Option<u64>,
+}
+
+// Collects every live key starting with `prefix`, sorted by key so exports
+// of the same store are identical.
+pub fn collect(store: &dyn Storage, prefix: &str) -> Vec<Record> {
+    let now = storage::now();
+    let mut records: Vec<Record> = store.scan(prefix)
+        .into_iter()
+        .filter(|entry| entry.expires_at.is_none_or(|expires_at| expires_at > now))
+        .map(|entry| Record {
+            value: serde_json::from_str(&entry.value).unwrap_or(Value::String(entry.value)),
+            key: entry.key,
+            ttl: entry.expires_at.map(|expires_at| expires_at - now),
+        })
+        .collect();
+    records.sort_by(|a, b| a.key.cmp(&b.key));
+    records
+}
+
+pub fn to_json_lines(records: &[Record]) -> String {
+    records
+        .iter()
+        .map(|record| serde_json::to_string(record).unwrap() + "\n")
+        .collect()
+}
+
+// Reads a whole export file up front so a malformed line aborts the import
+// before anything in the store has been changed.
+pub fn read_records(path: &Path) -> Result<Vec<Record>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            serde_json::from_str(line).map_err(|e| format!("Line {}: {}", number + 1, e))
+        })
+        .collect()
+}
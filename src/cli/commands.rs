@@ -1,126 +1,452 @@
-use std::io::{self, Write};
-use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
-use simple_kv_store::KvStore;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use serde_json::{json, Value};
+use rustyline::error::ReadlineError;
+use rustyline::history::FileHistory;
+use rustyline::Editor;
+use tracing::{debug, warn};
+use super::backup::{self, Record};
+use super::editor::{self, AdminHelper};
+use super::query::{self, KeyFilter, Page};
+use crate::core::{Board, BOARDS_KEY, BOARD_SIZE_KEY};
+use crate::storage::{self, Store};
 
-#[allow(dead_code)]
 pub struct CLI {
-    store: Arc<Mutex<KvStore>>,
+    store: Store,
 }
 
-pub fn split_command(input: &str) -> Vec<String> {
-    let mut parts = Vec::new();
+// Exit codes for `db exec` and `db run`
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_NOT_FOUND: i32 = 3;
+pub const EXIT_INVALID_VALUE: i32 = 4;
+
+#[derive(Debug)]
+pub enum CommandError {
+    UnknownCommand(String),
+    Usage(&'static str),
+    NotFound(String),
+    InvalidValue(String),
+    Store(String),
+}
+
+impl CommandError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CommandError::UnknownCommand(_) | CommandError::Usage(_) => EXIT_USAGE,
+            CommandError::NotFound(_) => EXIT_NOT_FOUND,
+            CommandError::InvalidValue(_) => EXIT_INVALID_VALUE,
+            CommandError::Store(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::UnknownCommand(command) => write!(f, "Unknown command \"{}\"", command),
+            CommandError::Usage(usage) => write!(f, "Usage: {}", usage),
+            CommandError::NotFound(key) => write!(f, "Key \"{}\" not found", key),
+            CommandError::InvalidValue(message) => write!(f, "{}", message),
+            CommandError::Store(message) => write!(f, "Store error: {}", message),
+        }
+    }
+}
+
+pub struct Entry {
+    key: String,
+    value: String,
+    // Seconds until the key expires; 0 once it has expired
+    expires_in: Option<u64>,
+}
+
+// The result of a successful command, printed as text or as JSON
+pub enum Output {
+    Set { key: String, value: String, ttl: Option<u64> },
+    Value { key: String, value: String },
+    Deleted { key: String, value: String },
+    // `total` counts the matching keys on every page, and `next` is the key
+    // to pass to --after for the following page
+    List { entries: Vec<Entry>, total: usize, next: Option<String> },
+    Count(usize),
+    Ttl { key: String, expires_in: Option<u64> },
+    HiscoreTtl(u64),
+    LeaderboardSize(usize),
+    Boards(Vec<Board>),
+    Export(Vec<Record>),
+    Exported { path: String, count: usize },
+    Imported { imported: usize, removed: usize },
+    Help,
+    Exit,
+}
+
+impl Output {
+    fn to_json(&self) -> Value {
+        match self {
+            Output::Set { key, value, ttl } => json!({ "key": key, "value": json_value(value), "ttl": ttl }),
+            Output::Value { key, value } | Output::Deleted { key, value } => {
+                json!({ "key": key, "value": json_value(value) })
+            }
+            Output::List { entries, total, next } => json!({
+                "entries": entries.iter().map(|entry| json!({
+                    "key": entry.key,
+                    "value": json_value(&entry.value),
+                    "expires_in": entry.expires_in,
+                })).collect::<Vec<_>>(),
+                "count": entries.len(),
+                "total": total,
+                "next": next,
+            }),
+            Output::Count(count) => json!({ "count": count }),
+            Output::Ttl { key, expires_in } => json!({ "key": key, "expires_in": expires_in }),
+            Output::HiscoreTtl(ttl) => json!({ "ttl": ttl }),
+            Output::LeaderboardSize(size) => json!({ "size": size }),
+            Output::Boards(boards) => json!({ "boards": boards }),
+            Output::Export(records) => json!({ "records": records }),
+            Output::Exported { path, count } => json!({ "path": path, "count": count }),
+            Output::Imported { imported, removed } => json!({ "imported": imported, "removed": removed }),
+            Output::Help => json!({ "commands": COMMANDS }),
+            Output::Exit => Value::Null,
+        }
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Output::Set { key, value, ttl } => write!(f, "Set \"{}\" = {}{}",
+                key,
+                value,
+                ttl.map_or(String::new(), |t| format!(" (expires in {} seconds)", t))
+            ),
+            Output::Value { key, value } => write!(f, "\"{}\" = \"{}\"", key, value),
+            Output::Deleted { key, value } => write!(f, "Deleted \"{}\" = \"{}\"", key, value),
+            Output::List { entries, .. } if entries.is_empty() => write!(f, "No matching keys"),
+            Output::List { entries, total, next } => {
+                writeln!(f, "Store contents:")?;
+                for entry in entries {
+                    let ttl_info = match entry.expires_in {
+                        None => String::new(),
+                        Some(0) => " (expired)".to_string(),
+                        Some(seconds) => format!(" (expires in {} seconds)", seconds),
+                    };
+                    writeln!(f, "  \"{}\" = \"{}\"{}", entry.key, entry.value, ttl_info)?;
+                }
+                if entries.len() == *total {
+                    write!(f, "Total items: {}", total)?;
+                } else {
+                    write!(f, "Total items: {} ({} shown)", total, entries.len())?;
+                }
+                if let Some(next) = next {
+                    write!(f, "\nMore keys follow; continue with --after \"{}\"", next)?;
+                }
+                Ok(())
+            }
+            Output::Count(count) => write!(f, "{}", count),
+            Output::Ttl { key, expires_in } => match expires_in {
+                None => write!(f, "\"{}\" never expires", key),
+                Some(0) => write!(f, "\"{}\" has expired", key),
+                Some(seconds) => write!(f, "\"{}\" expires in {} seconds", key, seconds),
+            },
+            Output::HiscoreTtl(ttl) => write!(f, "High score TTL set to {} seconds", ttl),
+            Output::LeaderboardSize(size) => write!(f, "Leaderboards keep the top {} scores", size),
+            Output::Boards(boards) => {
+                let names: Vec<String> = boards.iter().map(Board::to_string).collect();
+                write!(f, "Scores are recorded on: {}", names.join(", "))
+            }
+            Output::Export(records) => write!(f, "{}", backup::to_json_lines(records).trim_end()),
+            Output::Exported { path, count } => write!(f, "Exported {} keys to {}", count, path),
+            Output::Imported { imported, removed } => {
+                write!(f, "Imported {} keys", imported)?;
+                if *removed > 0 {
+                    write!(f, " (removed {} existing keys)", removed)?;
+                }
+                Ok(())
+            }
+            Output::Help => {
+                writeln!(f, "Simple Key-Value Store")?;
+                write!(f, "Available commands:")?;
+                for command in COMMANDS {
+                    write!(f, "\n  {}", command)?;
+                }
+                Ok(())
+            }
+            Output::Exit => Ok(()),
+        }
+    }
+}
+
+const COMMANDS: [&str; 15] = [
+    "set <key> <value> [ttl_seconds]",
+    "get <key>",
+    "delete <key>",
+    "list [glob] [--limit <n>] [--after <key>]",
+    "scan <prefix> [--limit <n>] [--after <key>]",
+    "count [prefix]",
+    "ttl <key>",
+    "expire <key> <seconds>",
+    "persist <key>",
+    "set-hiscore-ttl <seconds>",
+    "set-leaderboard-size <n>",
+    "set-boards <board>[,<board>...]",
+    "export [--prefix <prefix>] [file]",
+    "import [--prefix <prefix>] [--replace] <file>",
+    "exit",
+];
+
+const LIST_USAGE: &str = "list [glob] [--limit <n>] [--after <key>]";
+const SCAN_USAGE: &str = "scan <prefix> [--limit <n>] [--after <key>]";
+// Page size for scan when --limit is not given
+const SCAN_PAGE_SIZE: usize = 50;
+
+const EXPORT_USAGE: &str = "export [--prefix <prefix>] [file]";
+const IMPORT_USAGE: &str = "import [--prefix <prefix>] [--replace] <file>";
+
+struct BackupOptions {
+    prefix: String,
+    replace: bool,
+    file: Option<String>,
+}
+
+// Parses the options shared by export and import; `--replace` is import-only
+fn parse_backup_options(args: &[String], allow_replace: bool) -> Option<BackupOptions> {
+    let mut options = BackupOptions { prefix: String::new(), replace: false, file: None };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--prefix" => options.prefix = unquote(args.next()?),
+            "--replace" if allow_replace => options.replace = true,
+            _ if options.file.is_none() && !arg.starts_with("--") => options.file = Some(unquote(arg)),
+            _ => return None,
+        }
+    }
+    Some(options)
+}
+
+fn unquote(arg: &str) -> String {
+    arg.trim_matches(|c| c == '\'' || c == '"').to_string()
+}
+
+// Values are stored as JSON; anything that doesn't parse is reported as a string
+fn json_value(value: &str) -> Value {
+    serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+}
+
+// A command line split into words, with whatever quote or JSON bracket was
+// still open at its end so the editor knows to ask for more input
+pub struct Tokens {
+    pub parts: Vec<String>,
+    // The quote character of a string that was never closed
+    pub open_quote: Option<char>,
+    // Brackets opened outside quotes and not closed yet
+    pub depth: i32,
+}
+
+// Splits on spaces outside quotes, keeping the quotes in the words. A string
+// ends only at the quote character that opened it. Double quotes open one
+// anywhere, as inside JSON, but a single quote only at the start of a word,
+// so `don't` is a plain word.
+pub fn tokenize(input: &str) -> Tokens {
+    let mut tokens = Tokens { parts: Vec::new(), open_quote: None, depth: 0 };
     let mut current = String::new();
-    let mut in_quotes = false;
     let mut escaped = false;
 
     for c in input.chars() {
-        match c {
-            '\\' if !escaped => escaped = true,
-            '\'' | '"' if !escaped => {
-                in_quotes = !in_quotes;
+        if escaped {
+            if c != '\\' {
+                current.push('\\');
+            }
+            current.push(c);
+            escaped = false;
+            continue;
+        }
+
+        match (c, tokens.open_quote) {
+            ('\\', _) => escaped = true,
+            (_, Some(quote)) => {
+                if c == quote {
+                    tokens.open_quote = None;
+                }
                 current.push(c);
             }
-            ' ' if !in_quotes => {
+            ('"', None) => {
+                tokens.open_quote = Some(c);
+                current.push(c);
+            }
+            ('\'', None) if current.is_empty() => {
+                tokens.open_quote = Some(c);
+                current.push(c);
+            }
+            (' ', None) => {
                 if !current.is_empty() {
-                    parts.push(current);
-                    current = String::new();
+                    tokens.parts.push(std::mem::take(&mut current));
                 }
             }
-            _ => {
-                if escaped && c != '\\' {
-                    current.push('\\');
-                }
+            ('{' | '[', None) => {
+                tokens.depth += 1;
+                current.push(c);
+            }
+            ('}' | ']', None) => {
+                tokens.depth -= 1;
                 current.push(c);
-                escaped = false;
             }
+            _ => current.push(c),
         }
     }
 
     if !current.is_empty() {
-        parts.push(current);
+        tokens.parts.push(current);
     }
 
-    parts
+    tokens
+}
+
+pub fn split_command(input: &str) -> Vec<String> {
+    tokenize(input).parts
 }
 
-#[allow(dead_code)]
 impl CLI {
-    pub fn new(store: Arc<Mutex<KvStore>>) -> Self {
+    pub fn new(store: Store) -> Self {
         CLI { store }
     }
 
     pub fn run(&self) {
-        self.print_help();
+        println!("{}", Output::Help);
+
+        let config = rustyline::Config::builder()
+            .max_history_size(editor::HISTORY_SIZE)
+            .expect("Invalid history size")
+            .auto_add_history(false)
+            .build();
+        let mut editor: Editor<AdminHelper, FileHistory> = Editor::with_config(config)
+            .expect("Failed to start line editor");
+        editor.set_helper(Some(AdminHelper::new(self.store.clone())));
+
+        let history = editor::history_path();
+        // A missing history file just means this is the first session
+        if let Err(e) = editor.load_history(&history) {
+            debug!(path = %history.display(), error = %e, "no admin shell history loaded");
+        }
 
         loop {
-            if !self.process_command() {
+            if !self.process_command(&mut editor) {
                 break;
             }
         }
+
+        if let Err(e) = editor.save_history(&history) {
+            warn!(path = %history.display(), error = %e, "failed to save admin shell history");
+        }
     }
 
-    fn print_help(&self) {
-        println!("Simple Key-Value Store");
-        println!("Available commands:");
-        println!("  set <key> <value> [ttl_seconds]");
-        println!("  get <key>");
-        println!("  delete <key>");
-        println!("  list");
-        println!("  set-hiscore-ttl <seconds>");
-        println!("  exit");
+    // Runs a single command and returns the process exit code
+    pub fn exec(&self, input: &str, json: bool) -> i32 {
+        let parts = split_command(input);
+        let result = if parts.is_empty() {
+            Err(CommandError::Usage("db exec \"<command>\""))
+        } else {
+            self.execute(&parts)
+        };
+        report(&parts, &result, None, json);
+        result.map_or_else(|e| e.exit_code(), |_| EXIT_OK)
     }
 
-    fn process_command(&self) -> bool {
-        let mut input = String::new();
-        print!("> ");
-        io::stdout().flush().unwrap();
-        
-        io::stdin()
-            .read_line(&mut input)
-            .expect("Failed to read line");
+    // Runs a script of commands, one per line. Blank lines and lines starting
+    // with '#' are skipped; the script stops at the first failing command.
+    pub fn run_script(&self, path: &Path, json: bool) -> i32 {
+        let script = match fs::read_to_string(path) {
+            Ok(script) => script,
+            Err(e) => {
+                let error = CommandError::Store(format!("Cannot read {}: {}", path.display(), e));
+                report(&[], &Err(error), None, json);
+                return EXIT_FAILURE;
+            }
+        };
+
+        for (number, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parts = split_command(line);
+            let result = self.execute(&parts);
+            report(&parts, &result, Some(number + 1), json);
+            match result {
+                Ok(Output::Exit) => break,
+                Ok(_) => {}
+                Err(e) => return e.exit_code(),
+            }
+        }
+        EXIT_OK
+    }
+
+    fn process_command(&self, editor: &mut Editor<AdminHelper, FileHistory>) -> bool {
+        let input = match editor.readline("> ") {
+            Ok(input) => input,
+            // Ctrl-C discards the current line, Ctrl-D leaves the shell
+            Err(ReadlineError::Interrupted) => return true,
+            Err(ReadlineError::Eof) => return false,
+            Err(e) => {
+                println!("Error reading input: {}", e);
+                return false;
+            }
+        };
 
         let input = input.trim();
-        let parts = split_command(input);
+        if !input.is_empty() {
+            let _ = editor.add_history_entry(input);
+        }
+        let parts = split_command(&editor::join_lines(input));
 
         if parts.is_empty() {
             return true;
         }
 
-        match parts[0].as_str() {
-            "set" => {
-                let mut store = self.store.lock().unwrap();
-                self.handle_set(&parts, &mut store)
-            },
-            "get" => {
-                let mut store = self.store.lock().unwrap();
-                self.handle_get(&parts, &mut store)
-            },
-            "delete" => {
-                let mut store = self.store.lock().unwrap();
-                self.handle_delete(&parts, &mut store)
-            },
-            "list" => {
-                let store = self.store.lock().unwrap();
-                self.handle_list(&store)
-            },
-            "set-hiscore-ttl" => self.handle_set_hiscore_ttl(&parts),
-            "exit" => return false,
-            _ => self.print_help(),
+        match self.execute(&parts) {
+            Ok(Output::Exit) => return false,
+            Ok(output) => println!("{}", output),
+            Err(CommandError::UnknownCommand(_)) => println!("{}", Output::Help),
+            Err(e) => println!("{}", e),
         }
 
         true
     }
 
-    fn handle_set(&self, parts: &[String], store: &mut KvStore) {
+    fn execute(&self, parts: &[String]) -> Result<Output, CommandError> {
+        debug!(command = %parts[0], "running admin command");
+
+        match parts[0].as_str() {
+            "set" => self.handle_set(parts),
+            "get" => self.handle_get(parts),
+            "delete" => self.handle_delete(parts),
+            "list" => self.handle_list(parts),
+            "scan" => self.handle_scan(parts),
+            "count" => self.handle_count(parts),
+            "ttl" => self.handle_ttl(parts),
+            "expire" => self.handle_expire(parts),
+            "persist" => self.handle_persist(parts),
+            "set-hiscore-ttl" => self.handle_set_hiscore_ttl(parts),
+            "set-leaderboard-size" => self.handle_set_leaderboard_size(parts),
+            "set-boards" => self.handle_set_boards(parts),
+            "export" => self.handle_export(parts),
+            "import" => self.handle_import(parts),
+            "help" => Ok(Output::Help),
+            "exit" => Ok(Output::Exit),
+            command => Err(CommandError::UnknownCommand(command.to_string())),
+        }
+    }
+
+    fn handle_set(&self, parts: &[String]) -> Result<Output, CommandError> {
         if parts.len() < 3 {
-            println!("Usage: set <key> <value> [ttl_seconds]");
-            println!("Note: value must be a valid JSON string");
-            return;
+            return Err(CommandError::Usage("set <key> <value> [ttl_seconds] (value must be valid JSON)"));
         }
 
         let key = parts[1].clone();
-        
+
         // Find if the last part is a number (TTL)
         let (value, ttl) = if parts.len() > 3 {
             if let Ok(ttl) = parts.last().unwrap().parse::<u64>() {
@@ -138,88 +464,354 @@ impl CLI {
             let value = parts[2].trim_matches(|c| c == '\'' || c == '"');
             (value.to_string(), None)
         };
-        
-        match store.set_with_ttl(key.clone(), value.clone(), ttl) {
-            Ok(_) => println!("Set \"{}\" = {}{}", 
-                key, 
-                value,
-                ttl.map_or(String::new(), |t| format!(" (expires in {} seconds)", t))
-            ),
-            Err(e) => println!("Error: Invalid JSON value: {}", e),
+
+        match self.store.set(&key, &value, ttl) {
+            Ok(()) => Ok(Output::Set { key, value, ttl }),
+            Err(e) => Err(CommandError::InvalidValue(format!("Invalid JSON value: {}", e))),
         }
     }
 
-    fn handle_get(&self, parts: &[String], store: &mut KvStore) {
+    fn handle_get(&self, parts: &[String]) -> Result<Output, CommandError> {
         if parts.len() != 2 {
-            println!("Usage: get <key>");
-            return;
+            return Err(CommandError::Usage("get <key>"));
         }
-        match store.get(&parts[1]) {
-            Some(value) => println!("\"{}\" = \"{}\"", parts[1], value),
-            None => println!("Key \"{}\" not found", parts[1]),
+        match self.store.get(&parts[1]) {
+            Some(value) => Ok(Output::Value { key: parts[1].clone(), value }),
+            None => Err(CommandError::NotFound(parts[1].clone())),
         }
     }
 
-    fn handle_delete(&self, parts: &[String], store: &mut KvStore) {
+    fn handle_delete(&self, parts: &[String]) -> Result<Output, CommandError> {
         if parts.len() != 2 {
-            println!("Usage: delete <key>");
-            return;
+            return Err(CommandError::Usage("delete <key>"));
         }
         let key = &parts[1];
-        match store.delete(key) {
-            Ok(Some(value)) => println!("Deleted \"{}\" = \"{}\"", key, value),
-            Ok(None) => println!("Key \"{}\" not found", key),
-            Err(e) => println!("Error deleting key: {}", e),
+        match self.store.delete(key) {
+            Ok(Some(value)) => Ok(Output::Deleted { key: key.clone(), value }),
+            Ok(None) => Err(CommandError::NotFound(key.clone())),
+            Err(e) => Err(CommandError::Store(e.to_string())),
         }
     }
 
-    fn handle_list(&self, store: &KvStore) {
-        if store.is_empty() {
-            println!("Store is empty");
-            return;
+    fn handle_list(&self, parts: &[String]) -> Result<Output, CommandError> {
+        let (args, page) = query::parse_page(&parts[1..], None).ok_or(CommandError::Usage(LIST_USAGE))?;
+        let filter = match args.as_slice() {
+            [] => KeyFilter::All,
+            [pattern] => KeyFilter::Glob(pattern.clone()),
+            _ => return Err(CommandError::Usage(LIST_USAGE)),
+        };
+        Ok(self.page(&filter, &page))
+    }
+
+    fn handle_scan(&self, parts: &[String]) -> Result<Output, CommandError> {
+        let (args, page) = query::parse_page(&parts[1..], Some(SCAN_PAGE_SIZE))
+            .ok_or(CommandError::Usage(SCAN_USAGE))?;
+        match args.as_slice() {
+            [prefix] => Ok(self.page(&KeyFilter::Prefix(prefix.clone()), &page)),
+            _ => Err(CommandError::Usage(SCAN_USAGE)),
         }
+    }
 
-        println!("Store contents:");
-        for (key, value) in store.get_all() {
-            let ttl_info = value.expires_at.map_or(String::new(), |expires_at| {
-                let now = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                if expires_at > now {
-                    format!(" (expires in {} seconds)", expires_at - now)
-                } else {
-                    " (expired)".to_string()
-                }
-            });
-            println!("  \"{}\" = \"{}\"{}",
-                key,
-                value.data,
-                ttl_info
-            );
+    // Matching keys in key order, starting after `page.after`
+    fn page(&self, filter: &KeyFilter, page: &Page) -> Output {
+        let now = storage::now();
+
+        let all: Vec<_> = self.store.scan(filter.prefix())
+            .into_iter()
+            .filter(|entry| filter.matches(&entry.key))
+            .collect();
+        let total = all.len();
+
+        let mut matching: Vec<_> = all
+            .into_iter()
+            .filter(|entry| page.after.as_ref().is_none_or(|after| entry.key > *after))
+            .collect();
+        matching.sort_by(|a, b| a.key.cmp(&b.key));
+
+        let limit = page.limit.unwrap_or(matching.len());
+        let next = (matching.len() > limit).then(|| matching[limit - 1].key.clone());
+
+        let entries = matching
+            .into_iter()
+            .take(limit)
+            .map(|entry| Entry {
+                key: entry.key,
+                value: entry.value,
+                expires_in: entry.expires_at.map(|expires_at| expires_at.saturating_sub(now)),
+            })
+            .collect();
+        Output::List { entries, total, next }
+    }
+
+    fn handle_count(&self, parts: &[String]) -> Result<Output, CommandError> {
+        let filter = match parts.len() {
+            1 => KeyFilter::All,
+            2 => KeyFilter::Prefix(parts[1].clone()),
+            _ => return Err(CommandError::Usage("count [prefix]")),
+        };
+        Ok(Output::Count(self.store.scan(filter.prefix()).len()))
+    }
+
+    fn handle_ttl(&self, parts: &[String]) -> Result<Output, CommandError> {
+        if parts.len() != 2 {
+            return Err(CommandError::Usage("ttl <key>"));
+        }
+        let key = &parts[1];
+        let entry = self.entry(key).ok_or(CommandError::NotFound(key.clone()))?;
+        let now = storage::now();
+        Ok(Output::Ttl {
+            key: key.clone(),
+            expires_in: entry.expires_at.map(|expires_at| expires_at.saturating_sub(now)),
+        })
+    }
+
+    fn handle_expire(&self, parts: &[String]) -> Result<Output, CommandError> {
+        if parts.len() != 3 {
+            return Err(CommandError::Usage("expire <key> <seconds>"));
         }
-        println!("Total items: {}", store.len());
+        let seconds = parts[2].parse::<u64>().map_err(|_| {
+            CommandError::InvalidValue("Invalid TTL value. Please provide a positive number.".to_string())
+        })?;
+        self.reset_ttl(&parts[1], Some(seconds))
     }
 
-    fn handle_set_hiscore_ttl(&self, parts: &[String]) {
+    fn handle_persist(&self, parts: &[String]) -> Result<Output, CommandError> {
         if parts.len() != 2 {
-            println!("Usage: set-hiscore-ttl <seconds>");
-            return;
+            return Err(CommandError::Usage("persist <key>"));
         }
+        self.reset_ttl(&parts[1], None)
+    }
+
+    // The store only sets TTLs on write, so the current value is written back
+    fn reset_ttl(&self, key: &str, ttl: Option<u64>) -> Result<Output, CommandError> {
+        let entry = self.entry(key).ok_or(CommandError::NotFound(key.to_string()))?;
+        self.store.set(key, &entry.value, ttl)
+            .map_err(|e| CommandError::Store(e.to_string()))?;
+        Ok(Output::Ttl { key: key.to_string(), expires_in: ttl })
+    }
+
+    // A single key together with its expiry
+    fn entry(&self, key: &str) -> Option<storage::Entry> {
+        self.store.scan(key).into_iter().find(|entry| entry.key == key)
+    }
+
+    fn handle_set_hiscore_ttl(&self, parts: &[String]) -> Result<Output, CommandError> {
+        if parts.len() != 2 {
+            return Err(CommandError::Usage("set-hiscore-ttl <seconds>"));
+        }
+
+        let ttl = parts[1].parse::<u64>().map_err(|_| {
+            CommandError::InvalidValue("Invalid TTL value. Please provide a positive number.".to_string())
+        })?;
+
+        self.store.set("hiscore_ttl", &ttl.to_string(), None)
+            .map_err(|e| CommandError::Store(e.to_string()))?;
+        Ok(Output::HiscoreTtl(ttl))
+    }
 
-        if let Ok(ttl) = parts[1].parse::<u64>() {
-            // Drop the store lock before creating ScoreManager
-            {
-                let mut store = self.store.lock().unwrap();
-                store.set_with_ttl(
-                    "hiscore_ttl".to_string(),
-                    ttl.to_string(),
-                    None,
-                ).expect("Failed to save high score TTL");
+    fn handle_set_leaderboard_size(&self, parts: &[String]) -> Result<Output, CommandError> {
+        if parts.len() != 2 {
+            return Err(CommandError::Usage("set-leaderboard-size <n>"));
+        }
+
+        let size = parts[1].parse::<usize>().ok().filter(|&size| size > 0).ok_or_else(|| {
+            CommandError::InvalidValue("Invalid size. Please provide a number greater than zero.".to_string())
+        })?;
+
+        self.store.set(BOARD_SIZE_KEY, &size.to_string(), None)
+            .map_err(|e| CommandError::Store(e.to_string()))?;
+        Ok(Output::LeaderboardSize(size))
+    }
+
+    fn handle_set_boards(&self, parts: &[String]) -> Result<Output, CommandError> {
+        if parts.len() != 2 {
+            return Err(CommandError::Usage("set-boards <board>[,<board>...] (all-time, daily, weekly, rolling)"));
+        }
+
+        let mut boards = Vec::new();
+        for name in parts[1].split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let board = name.parse::<Board>().map_err(CommandError::InvalidValue)?;
+            if !boards.contains(&board) {
+                boards.push(board);
             }
-            println!("High score TTL set to {} seconds", ttl);
-        } else {
-            println!("Invalid TTL value. Please provide a positive number.");
         }
+        if boards.is_empty() {
+            return Err(CommandError::InvalidValue("Enable at least one board.".to_string()));
+        }
+
+        let json = serde_json::to_string(&boards).unwrap();
+        self.store.set(BOARDS_KEY, &json, None)
+            .map_err(|e| CommandError::Store(e.to_string()))?;
+        Ok(Output::Boards(boards))
+    }
+
+    fn handle_export(&self, parts: &[String]) -> Result<Output, CommandError> {
+        let options = parse_backup_options(&parts[1..], false)
+            .ok_or(CommandError::Usage(EXPORT_USAGE))?;
+
+        let records = backup::collect(self.store.as_ref(), &options.prefix);
+
+        match options.file {
+            None => Ok(Output::Export(records)),
+            Some(path) => {
+                fs::write(&path, backup::to_json_lines(&records))
+                    .map_err(|e| CommandError::Store(format!("Cannot write {}: {}", path, e)))?;
+                Ok(Output::Exported { path, count: records.len() })
+            }
+        }
+    }
+
+    // Merging overwrites keys present in the file and leaves the rest alone.
+    // Replacing first deletes every existing key under the prefix.
+    fn handle_import(&self, parts: &[String]) -> Result<Output, CommandError> {
+        let options = parse_backup_options(&parts[1..], true)
+            .ok_or(CommandError::Usage(IMPORT_USAGE))?;
+        let path = options.file.ok_or(CommandError::Usage(IMPORT_USAGE))?;
+
+        let records: Vec<Record> = backup::read_records(Path::new(&path))
+            .map_err(CommandError::InvalidValue)?
+            .into_iter()
+            .filter(|record| record.key.starts_with(&options.prefix))
+            .collect();
+
+        // Every value is checked before anything is written
+        let staged: Vec<(&str, String, Option<u64>)> = records
+            .iter()
+            .map(|record| {
+                let value = record.value.to_string();
+                storage::validate(&value)
+                    .map_err(|e| CommandError::InvalidValue(format!("{}: {}", record.key, e)))?;
+                Ok((record.key.as_str(), value, record.ttl))
+            })
+            .collect::<Result<_, CommandError>>()?;
+
+        // Keys are written first, keeping what they held before so a failed
+        // write can put the store back as it was
+        let mut previous = Vec::new();
+        for (key, value, ttl) in &staged {
+            let entry = self.entry(key);
+            if let Err(e) = self.store.set(key, value, *ttl) {
+                self.restore(previous);
+                return Err(CommandError::Store(format!("{}: {}", key, e)));
+            }
+            previous.push((key.to_string(), entry));
+        }
+
+        // Only once the import has landed are keys missing from it removed
+        let mut removed = 0;
+        if options.replace {
+            for entry in self.store.scan(&options.prefix) {
+                if staged.iter().any(|(key, _, _)| *key == entry.key) {
+                    continue;
+                }
+                self.store.delete(&entry.key).map_err(|e| CommandError::Store(format!("{}: {}", entry.key, e)))?;
+                removed += 1;
+            }
+        }
+        Ok(Output::Imported { imported: staged.len(), removed })
+    }
+
+    // Puts back keys overwritten by a failed import, newest first
+    fn restore(&self, previous: Vec<(String, Option<storage::Entry>)>) {
+        let now = storage::now();
+        for (key, entry) in previous.into_iter().rev() {
+            let result = match entry {
+                Some(entry) => {
+                    let ttl = entry.expires_at.map(|expires_at| expires_at.saturating_sub(now).max(1));
+                    self.store.set(&key, &entry.value, ttl)
+                }
+                None => self.store.delete(&key).map(|_| ()),
+            };
+            if let Err(e) = result {
+                warn!(key = %key, error = %e, "failed to restore key after a failed import");
+            }
+        }
+    }
+}
+
+// Prints the outcome of a non-interactive command. Text output goes to stdout
+// and errors to stderr; with `json` every command prints one JSON object per line.
+fn report(parts: &[String], result: &Result<Output, CommandError>, line: Option<usize>, json: bool) {
+    if json {
+        let mut record = match result {
+            Ok(output) => json!({ "ok": true, "result": output.to_json() }),
+            Err(e) => json!({ "ok": false, "error": e.to_string(), "exit_code": e.exit_code() }),
+        };
+        if let Some(command) = parts.first() {
+            record["command"] = json!(command);
+        }
+        if let Some(line) = line {
+            record["line"] = json!(line);
+        }
+        println!("{}", record);
+        return;
     }
-} 
\ No newline at end of file
+
+    match (result, line) {
+        (Ok(Output::Exit), _) => {}
+        (Ok(output), _) => println!("{}", output),
+        (Err(e), Some(line)) => eprintln!("Error on line {}: {}", line, e),
+        (Err(e), None) => eprintln!("Error: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::storage::MemoryStorage;
+    use super::*;
+
+    fn cli() -> CLI {
+        CLI::new(Arc::new(MemoryStorage::new()))
+    }
+
+    fn run(cli: &CLI, input: &str) -> Result<Output, CommandError> {
+        cli.execute(&split_command(input))
+    }
+
+    #[test]
+    fn apostrophes_inside_words_are_not_quotes() {
+        let tokens = tokenize("set note don't");
+        assert_eq!(tokens.parts, ["set", "note", "don't"]);
+        assert_eq!(tokens.open_quote, None);
+
+        let tokens = tokenize(r#"set note '{"a": "b c"}'"#);
+        assert_eq!(tokens.parts, ["set", "note", r#"'{"a": "b c"}'"#]);
+        assert_eq!((tokens.open_quote, tokens.depth), (None, 0));
+
+        let tokens = tokenize(r#"set note "unfinished 'string"#);
+        assert_eq!(tokens.open_quote, Some('"'));
+    }
+
+    #[test]
+    fn list_total_counts_every_page() {
+        let cli = cli();
+        for key in ["a", "b", "c", "d", "e"] {
+            run(&cli, &format!("set {} 1", key)).unwrap();
+        }
+
+        let Output::List { entries, total, next } = run(&cli, "list --limit 2 --after b").unwrap() else {
+            panic!("expected a list");
+        };
+        assert_eq!(entries.len(), 2);
+        assert_eq!(total, 5);
+        assert_eq!(next.as_deref(), Some("d"));
+    }
+
+    #[test]
+    fn import_replace_keeps_imported_keys_and_drops_the_rest() {
+        let cli = cli();
+        run(&cli, "set cfg:a 1").unwrap();
+        run(&cli, "set cfg:b 2").unwrap();
+        let path = std::env::temp_dir().join(format!("side_scroller-{}.json", uuid::Uuid::new_v4().simple()));
+        fs::write(&path, concat!(r#"{"key":"cfg:a","value":10}"#, "\n", r#"{"key":"cfg:c","value":30}"#, "\n")).unwrap();
+
+        let output = run(&cli, &format!("import --prefix cfg: --replace {}", path.display())).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(output, Output::Imported { imported: 2, removed: 1 }));
+        assert_eq!(cli.store.get("cfg:a").as_deref(), Some("10"));
+        assert_eq!(cli.store.get("cfg:b"), None);
+        assert_eq!(cli.store.get("cfg:c").as_deref(), Some("30"));
+    }
+}
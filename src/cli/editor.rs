@@ -0,0 +1,105 @@
+use std::path::PathBuf;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Helper};
+use super::commands::tokenize;
+use crate::storage::Store;
+
+const HISTORY_FILE: &str = ".side_scroller_history";
+pub const HISTORY_SIZE: usize = 1000;
+
+// Command names offered when completing the first word of a line
+const COMMAND_NAMES: [&str; 16] = [
+    "set", "get", "delete", "list", "scan", "count", "ttl", "expire", "persist",
+    "set-hiscore-ttl", "set-leaderboard-size", "set-boards", "export", "import", "help", "exit",
+];
+
+// Commands whose first argument is an existing key
+const KEY_COMMANDS: [&str; 8] = ["set", "get", "delete", "scan", "count", "ttl", "expire", "persist"];
+
+// History lives in the home directory so it is shared between sessions
+// started from different working directories.
+pub fn history_path() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(HISTORY_FILE)
+}
+
+// Folds multi-line input into a single command line. A backslash before a
+// line break joins the lines without adding a space.
+pub fn join_lines(input: &str) -> String {
+    input.replace("\\\n", "").replace('\n', " ")
+}
+
+pub struct AdminHelper {
+    store: Store,
+}
+
+impl AdminHelper {
+    pub fn new(store: Store) -> Self {
+        AdminHelper { store }
+    }
+}
+
+impl Completer for AdminHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos].rfind(' ').map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        let previous: Vec<&str> = line[..start].split_whitespace().collect();
+
+        let candidates: Vec<String> = match previous.as_slice() {
+            [] => COMMAND_NAMES
+                .iter()
+                .filter(|name| name.starts_with(word))
+                .map(|name| name.to_string())
+                .collect(),
+            [command] if KEY_COMMANDS.contains(command) => {
+                let mut keys: Vec<String> = self.store.scan(word)
+                    .into_iter()
+                    .map(|entry| entry.key)
+                    .collect();
+                keys.sort();
+                keys
+            }
+            _ => Vec::new(),
+        };
+
+        let pairs = candidates
+            .into_iter()
+            .map(|candidate| Pair { display: candidate.clone(), replacement: candidate })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+// Multi-line input ends once quotes and JSON brackets are balanced.
+// A trailing backslash continues the line explicitly.
+impl Validator for AdminHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let input = ctx.input();
+        if input.ends_with('\\') {
+            return Ok(ValidationResult::Incomplete);
+        }
+
+        // Checked the way the joined line will be split when it runs
+        let tokens = tokenize(&join_lines(input));
+        if tokens.open_quote.is_some() || tokens.depth > 0 {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Hinter for AdminHelper {
+    type Hint = String;
+}
+
+impl Highlighter for AdminHelper {}
+
+impl Helper for AdminHelper {}
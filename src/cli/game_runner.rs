@@ -1,62 +1,247 @@
 use std::{thread, time::Duration};
-use std::sync::{Arc, Mutex};
 use crossterm::{
     execute,
     terminal::{enable_raw_mode, disable_raw_mode},
     cursor::{Hide, Show},
 };
 use std::io::stdout;
-use simple_kv_store::KvStore;
-use crate::core::Game;
-use crate::ui::{render_game, handle_input, ask_play_again};
+use tracing::info;
+use crate::core::{
+    format_date, Achievement, AchievementManager, Board, Challenge, ChallengeManager, Game, Placement, Player,
+    PlayerManager, ProfileManager, Replay, ReplayManager, ScoreManager,
+};
+use crate::ui::{announce_achievements, render_game, render_profile, render_score_breakdown, handle_input, ask_play_again};
+use crate::storage::Store;
 use crate::{FRAME_DURATION};
 
 pub struct GameRunner {
-    store: Arc<Mutex<KvStore>>,
+    store: Store,
+    seed: Option<u64>,
 }
 
 impl GameRunner {
-    pub fn new(store: Arc<Mutex<KvStore>>) -> Self {
-        Self { store }
+    pub fn new(store: Store) -> Self {
+        Self { store, seed: None }
+    }
+
+    // Every game in this session uses the given seed instead of a random one
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
     }
 
     pub fn run(&self) {
+        // Asked for up front so every game of the session counts towards the
+        // player's stats, not just the ones that make a board
+        let player = ScoreManager::new(self.store.clone()).prompt_player();
+
         loop {
-            let mut game = Game::new(self.store.clone());
-            
-            enable_raw_mode().unwrap();
-            execute!(stdout(), Hide).unwrap();
-            
-            while !game.get_state().is_game_over {
-                if let Some(movement) = handle_input(Duration::from_millis(10)) {
-                    game.handle_input(movement);
-                }
-                
-                render_game(&game.get_state());
-                game.update();
-                thread::sleep(FRAME_DURATION);
-            }
-            
-            // Restore normal terminal mode for input
-            disable_raw_mode().unwrap();
-            execute!(stdout(), Show).unwrap();
-            
-            let high_scores = game.handle_game_over();
-            println!("\nGame Over! Final score: {}", game.get_state().score);
-            
-            if !high_scores.is_empty() {
-                println!("\nHigh Scores:");
-                for (i, (name, score, ttl)) in high_scores.iter().enumerate() {
-                    let ttl_info = ttl.map_or(String::new(), |t| format!(" (expires in {}s)", t));
-                    println!("{}. {} - {}{}", i + 1, name, score, ttl_info);
-                }
-            }
-            
+            let game = match self.seed {
+                Some(seed) => Game::with_seed(self.store.clone(), seed),
+                None => Game::new(self.store.clone()),
+            };
+            let (game, replay) = self.play(game);
+
+            self.finish(&player, &game, &replay);
             println!();
-            
+
             if !ask_play_again() {
                 break;
             }
         }
     }
-} 
\ No newline at end of file
+
+    // One attempt at today's challenge. The player is asked for up front
+    // because only their first attempt of the day is allowed.
+    pub fn run_daily(&self) {
+        let challenge = Challenge::today();
+        println!("Daily challenge for {}", challenge.date);
+        let player = ScoreManager::new(self.store.clone()).prompt_player();
+
+        let challenges = ChallengeManager::new(self.store.clone());
+        if let Err(e) = challenges.start(&player.id, &challenge) {
+            println!("{}", e);
+            print_high_scores(&challenges.standings(&challenge));
+            return;
+        }
+
+        let (game, replay) = self.play(Game::with_seed(self.store.clone(), challenge.seed));
+        let unlocked = self.record_run(&player, &game, &replay);
+        let rank = challenges.finish(&player, &challenge, &game.get_state().breakdown);
+
+        announce_achievements(&unlocked);
+        println!("You placed #{} in the {} challenge", rank, challenge.date);
+        print_high_scores(&challenges.standings(&challenge));
+        println!();
+        self.show_profile(&player);
+    }
+
+    // Runs a game to the end in raw mode and saves its replay
+    fn play(&self, mut game: Game) -> (Game, Replay) {
+        let mut inputs = Vec::new();
+
+        enable_raw_mode().unwrap();
+        execute!(stdout(), Hide).unwrap();
+
+        while !game.get_state().is_game_over {
+            if let Some(movement) = handle_input(Duration::from_millis(10)) {
+                inputs.push((game.tick(), movement));
+                game.handle_input(movement);
+            }
+
+            render_game(&game.get_state());
+            game.update();
+            thread::sleep(FRAME_DURATION);
+        }
+
+        // Restore normal terminal mode for input
+        disable_raw_mode().unwrap();
+        execute!(stdout(), Show).unwrap();
+
+        info!(score = game.get_state().score, ticks = game.tick(), "game over");
+        let replay = ReplayManager::new(self.store.clone())
+            .save(game.seed(), inputs, game.get_state().score);
+
+        render_score_breakdown(&game.get_state().breakdown);
+        println!("Replay saved as {}", replay.id);
+        println!();
+        (game, replay)
+    }
+
+    // Records a finished game for the player and shows where it left them
+    fn finish(&self, player: &Player, game: &Game, replay: &Replay) {
+        let unlocked = self.record_run(player, game, replay);
+        let outcome = game.handle_game_over(player);
+
+        announce_achievements(&unlocked);
+        print_placements(&outcome.placements);
+        print_high_scores(&outcome.hiscores);
+        println!();
+        self.show_profile(player);
+    }
+
+    // Updates the player's profile and returns the achievements they earned
+    fn record_run(&self, player: &Player, game: &Game, replay: &Replay) -> Vec<&'static Achievement> {
+        let state = game.get_state();
+        ProfileManager::new(self.store.clone()).record_run(&player.id, &state, game.tick(), Some(&replay.id));
+        AchievementManager::new(self.store.clone()).record(&player.id, &state.achievements)
+    }
+
+    fn show_profile(&self, player: &Player) {
+        let profile = ProfileManager::new(self.store.clone()).get(&player.id);
+        let earned = AchievementManager::new(self.store.clone()).earned(&player.id);
+        render_profile(&player.name, &profile, &earned);
+    }
+
+    // Plays back a recorded game, or the most recent one when no id is given
+    pub fn replay(&self, id: Option<&str>) -> Result<(), String> {
+        let replays = ReplayManager::new(self.store.clone());
+        let replay = match id {
+            Some(id) => replays.get(id).ok_or(format!("No replay with id {}", id))?,
+            None => replays.list().into_iter().next().ok_or("No replays recorded yet")?,
+        };
+
+        execute!(stdout(), Hide).unwrap();
+        let score = self.play_back(&replay);
+        execute!(stdout(), Show).unwrap();
+
+        println!("\nReplay {} finished. Score: {}", replay.id, score);
+        Ok(())
+    }
+
+    fn play_back(&self, replay: &Replay) -> u32 {
+        let mut game = Game::with_seed(self.store.clone(), replay.seed);
+        let mut inputs = replay.inputs.iter().peekable();
+
+        while !game.get_state().is_game_over {
+            while let Some((_, movement)) = inputs.next_if(|(tick, _)| *tick == game.tick()) {
+                game.handle_input(*movement);
+            }
+
+            render_game(&game.get_state());
+            game.update();
+            thread::sleep(FRAME_DURATION);
+        }
+
+        render_game(&game.get_state());
+        game.get_state().score
+    }
+
+    pub fn list_replays(&self) {
+        let replays = ReplayManager::new(self.store.clone()).list();
+        if replays.is_empty() {
+            println!("No replays recorded yet");
+            return;
+        }
+
+        println!("Replays (newest first):");
+        for replay in replays {
+            println!("  {} - score {}", replay.id, replay.score);
+        }
+    }
+
+    pub fn show_scores(&self, board: Option<Board>) {
+        let scores = ScoreManager::new(self.store.clone());
+        let board = board.unwrap_or_else(|| scores.default_board());
+        let high_scores = scores.top_scores(board);
+        if high_scores.is_empty() {
+            println!("No high scores on the {} board yet", board);
+        } else {
+            print_high_scores(&high_scores);
+        }
+    }
+
+    pub fn show_challenge(&self) {
+        let challenge = Challenge::today();
+        let standings = ChallengeManager::new(self.store.clone()).standings(&challenge);
+        if standings.is_empty() {
+            println!("Nobody has finished the {} challenge yet", challenge.date);
+        } else {
+            println!("Daily challenge for {}", challenge.date);
+            print_high_scores(&standings);
+        }
+    }
+
+    pub fn show_stats(&self, name: &str) {
+        let Some(player) = PlayerManager::new(self.store.clone()).find_by_name(name) else {
+            println!("No player named {}", name);
+            return;
+        };
+
+        self.show_profile(&player);
+    }
+
+    pub fn show_player_scores(&self, name: &str) {
+        let Some(player) = PlayerManager::new(self.store.clone()).find_by_name(name) else {
+            println!("No player named {}", name);
+            return;
+        };
+
+        let history = ScoreManager::new(self.store.clone()).player_history(&player.id);
+        if history.is_empty() {
+            println!("{} has no high scores on record", player.name);
+            return;
+        }
+
+        println!("High scores for {} (newest first):", player.name);
+        for (score, timestamp) in history {
+            println!("  {} ({})", score, format_date(timestamp));
+        }
+    }
+}
+
+fn print_placements(placements: &[Placement]) {
+    for placement in placements {
+        println!("You placed #{} on the {} board", placement.rank, placement.board);
+    }
+}
+
+fn print_high_scores(high_scores: &[(String, u32, Option<u64>)]) {
+    if !high_scores.is_empty() {
+        println!("\nHigh Scores:");
+        for (i, (name, score, ttl)) in high_scores.iter().enumerate() {
+            let ttl_info = ttl.map_or(String::new(), |t| format!(" (expires in {}s)", t));
+            println!("{}. {} - {}{}", i + 1, name, score, ttl_info);
+        }
+    }
+}
@@ -1,5 +1,10 @@
+mod args;
+mod backup;
 mod commands;
+mod editor;
 mod game_runner;
+mod query;
 
+pub use args::{Args, Command, DbArgs, DbCommand, PlayArgs, ServeArgs};
 pub use commands::CLI;
-pub use game_runner::GameRunner; 
\ No newline at end of file
+pub use game_runner::GameRunner;
@@ -0,0 +1,81 @@
+// Selects keys for list, scan and count
+pub enum KeyFilter {
+    All,
+    Glob(String),
+    Prefix(String),
+}
+
+impl KeyFilter {
+    // The longest literal prefix every matching key starts with, so the
+    // store only has to scan that part of the key space
+    pub fn prefix(&self) -> &str {
+        match self {
+            KeyFilter::All => "",
+            KeyFilter::Glob(pattern) => {
+                let end = pattern.find(['*', '?']).unwrap_or(pattern.len());
+                &pattern[..end]
+            }
+            KeyFilter::Prefix(prefix) => prefix,
+        }
+    }
+
+    pub fn matches(&self, key: &str) -> bool {
+        match self {
+            KeyFilter::All => true,
+            KeyFilter::Glob(pattern) => glob_match(pattern, key),
+            KeyFilter::Prefix(prefix) => key.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+// Matches `*` (any run of characters) and `?` (any single character)
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen and the text position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, start)) = backtrack {
+            // Let the last `*` swallow one more character and retry
+            p = star + 1;
+            t = start + 1;
+            backtrack = Some((star, start + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Keyset pagination: at most `limit` keys that sort after `after`
+pub struct Page {
+    pub limit: Option<usize>,
+    pub after: Option<String>,
+}
+
+// Splits `--limit <n>` and `--after <key>` from the positional arguments.
+// Returns None on unknown options or a malformed limit.
+pub fn parse_page(args: &[String], default_limit: Option<usize>) -> Option<(Vec<String>, Page)> {
+    let mut positional = Vec::new();
+    let mut page = Page { limit: default_limit, after: None };
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--limit" => page.limit = Some(args.next()?.parse().ok().filter(|&n| n > 0)?),
+            "--after" => page.after = Some(args.next()?.trim_matches(|c| c == '\'' || c == '"').to_string()),
+            _ if arg.starts_with("--") => return None,
+            _ => positional.push(arg.trim_matches(|c| c == '\'' || c == '"').to_string()),
+        }
+    }
+    Some((positional, page))
+}
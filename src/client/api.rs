@@ -0,0 +1,229 @@
+use std::error::Error;
+use reqwest::{Client, RequestBuilder, Response};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use crate::core::{GameState, PlayerMove};
+use crate::server::API_BASE;
+
+#[derive(Deserialize)]
+pub struct Registration {
+    pub name: String,
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+struct NewGameResponse {
+    game_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct HiScoreEntry {
+    pub name: String,
+    pub score: u32,
+    pub expires_in: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct PlacementEntry {
+    pub board: String,
+    pub rank: usize,
+}
+
+#[derive(Deserialize)]
+pub struct HiScoresResponse {
+    pub hiscores: Vec<HiScoreEntry>,
+    #[serde(default)]
+    pub placements: Vec<PlacementEntry>,
+}
+
+#[derive(Deserialize)]
+struct NewRoomResponse {
+    room_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct RacerSummary {
+    pub name: String,
+    #[serde(default)]
+    pub forfeited: bool,
+}
+
+#[derive(Deserialize)]
+pub struct RankingEntry {
+    pub rank: usize,
+    pub name: String,
+    pub score: u32,
+}
+
+#[derive(Deserialize)]
+pub struct RoomView {
+    pub status: String,
+    pub players: Vec<RacerSummary>,
+    pub ranking: Option<Vec<RankingEntry>>,
+}
+
+#[derive(Deserialize)]
+pub struct Ghost {
+    pub lane: usize,
+    pub column: Option<usize>,
+    pub alive: bool,
+}
+
+#[derive(Deserialize)]
+pub struct RaceView {
+    pub room: RoomView,
+    pub state: GameState,
+    pub ghosts: Vec<Ghost>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+pub struct ApiClient {
+    http: Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl ApiClient {
+    pub fn new(server_url: &str) -> Self {
+        Self {
+            http: Client::new(),
+            base_url: format!("{}{}", server_url.trim_end_matches('/'), API_BASE),
+            token: None,
+        }
+    }
+
+    pub fn set_token(&mut self, token: String) {
+        self.token = Some(token);
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    // Decodes a successful JSON response, or turns the server's
+    // ErrorResponse into an error message.
+    async fn parse<T: DeserializeOwned>(response: Response) -> Result<T, Box<dyn Error>> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response.json().await?);
+        }
+
+        let message = response
+            .json::<ErrorResponse>()
+            .await
+            .map(|e| e.error)
+            .unwrap_or_else(|_| status.to_string());
+        Err(message.into())
+    }
+
+    pub async fn register(&mut self, name: &str) -> Result<Registration, Box<dyn Error>> {
+        let response = self.http
+            .post(self.url("/players"))
+            .json(&json!({ "name": name }))
+            .send()
+            .await?;
+        let registration: Registration = Self::parse(response).await?;
+        self.token = Some(registration.token.clone());
+        Ok(registration)
+    }
+
+    pub async fn new_game(&self) -> Result<String, Box<dyn Error>> {
+        let response = self.authorized(self.http.post(self.url("/game/new")))
+            .send()
+            .await?;
+        let new_game: NewGameResponse = Self::parse(response).await?;
+        Ok(new_game.game_id)
+    }
+
+    pub async fn make_move(
+        &self,
+        game_id: &str,
+        movement: Option<PlayerMove>,
+    ) -> Result<GameState, Box<dyn Error>> {
+        let response = self.authorized(self.http.post(self.url(&format!("/game/{}/move", game_id))))
+            .json(&move_body(movement))
+            .send()
+            .await?;
+        Self::parse(response).await
+    }
+
+    pub async fn submit_score(&self, game_id: &str) -> Result<HiScoresResponse, Box<dyn Error>> {
+        let response = self.authorized(self.http.post(self.url(&format!("/game/{}/score", game_id))))
+            .send()
+            .await?;
+        Self::parse(response).await
+    }
+
+    pub async fn watch(&self, game_id: &str) -> Result<Response, reqwest::Error> {
+        self.http
+            .get(self.url(&format!("/game/{}/watch", game_id)))
+            .header("accept", "text/event-stream")
+            .send()
+            .await?
+            .error_for_status()
+    }
+
+    pub async fn new_room(&self) -> Result<String, Box<dyn Error>> {
+        let response = self.authorized(self.http.post(self.url("/rooms")))
+            .send()
+            .await?;
+        let new_room: NewRoomResponse = Self::parse(response).await?;
+        Ok(new_room.room_id)
+    }
+
+    pub async fn join_room(&self, room_id: &str) -> Result<RoomView, Box<dyn Error>> {
+        let response = self.authorized(self.http.post(self.url(&format!("/rooms/{}/join", room_id))))
+            .send()
+            .await?;
+        Self::parse(response).await
+    }
+
+    pub async fn start_room(&self, room_id: &str) -> Result<RoomView, Box<dyn Error>> {
+        let response = self.authorized(self.http.post(self.url(&format!("/rooms/{}/start", room_id))))
+            .send()
+            .await?;
+        Self::parse(response).await
+    }
+
+    // The caller's view of a room they are racing in
+    pub async fn race(&self, room_id: &str) -> Result<RaceView, Box<dyn Error>> {
+        let response = self.authorized(self.http.get(self.url(&format!("/rooms/{}", room_id))))
+            .send()
+            .await?;
+        Self::parse(response).await
+    }
+
+    pub async fn race_move(
+        &self,
+        room_id: &str,
+        movement: Option<PlayerMove>,
+    ) -> Result<RaceView, Box<dyn Error>> {
+        let response = self.authorized(self.http.post(self.url(&format!("/rooms/{}/move", room_id))))
+            .json(&move_body(movement))
+            .send()
+            .await?;
+        Self::parse(response).await
+    }
+}
+
+fn move_body(movement: Option<PlayerMove>) -> serde_json::Value {
+    let movement = match movement {
+        Some(PlayerMove::Up) => "up",
+        Some(PlayerMove::Down) => "down",
+        Some(PlayerMove::Quit) => "quit",
+        None => "stay",
+    };
+    json!({ "movement": movement })
+}
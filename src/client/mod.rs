@@ -0,0 +1,9 @@
+mod api;
+mod race;
+mod remote;
+mod watch;
+
+pub use api::ApiClient;
+pub use race::run_race;
+pub use remote::run_remote;
+pub use watch::run_watch;
@@ -0,0 +1,112 @@
+use std::error::Error;
+use std::io::{self, stdout, Write};
+use std::time::Duration;
+use crossterm::{
+    execute,
+    terminal::{enable_raw_mode, disable_raw_mode},
+    cursor::{Hide, Show},
+};
+use tracing::{info, warn};
+use crate::client::api::{RaceView, RoomView};
+use crate::client::remote::register;
+use crate::client::ApiClient;
+use crate::ui::{render_game_with_ghosts, render_score_breakdown, handle_input};
+use crate::FRAME_DURATION;
+
+// Races other players on a remote server. Without a room id a new room is
+// opened and the caller, as host, starts it once the others have joined.
+pub async fn run_race(server_url: &str, token: Option<String>, room_id: Option<String>) -> Result<(), Box<dyn Error>> {
+    let mut client = ApiClient::new(server_url);
+    match token {
+        Some(token) => client.set_token(token),
+        None => register(&mut client).await?,
+    }
+
+    let room_id = match room_id {
+        Some(room_id) => {
+            client.join_room(&room_id).await?;
+            println!("Joined room {}. Waiting for the host to start the race...", room_id);
+            wait_until(&client, &room_id, |room| room.status != "waiting").await?;
+            room_id
+        }
+        None => {
+            let room_id = client.new_room().await?;
+            println!("Opened room {}. Others join with: connect {} --room {}", room_id, server_url, room_id);
+            host_start(&client, &room_id).await?;
+            room_id
+        }
+    };
+    info!(server = server_url, room_id = %room_id, "race started");
+
+    enable_raw_mode()?;
+    execute!(stdout(), Hide)?;
+
+    let result = race(&client, &room_id).await;
+
+    // Restore normal terminal mode even if the connection failed
+    disable_raw_mode()?;
+    execute!(stdout(), Show)?;
+
+    let view = result.inspect_err(|e| warn!(error = %e, "race aborted"))?;
+    render_score_breakdown(&view.state.breakdown);
+
+    println!("\nWaiting for the other racers to finish...");
+    let room = wait_until(&client, &room_id, |room| room.ranking.is_some()).await?;
+    println!("\nFinal ranking:");
+    for entry in room.ranking.unwrap_or_default() {
+        let forfeited = room.players
+            .iter()
+            .any(|racer| racer.name == entry.name && racer.forfeited);
+        let note = if forfeited { " (forfeited)" } else { "" };
+        println!("{}. {} - {}{}", entry.rank, entry.name, entry.score, note);
+    }
+    println!();
+    Ok(())
+}
+
+async fn host_start(client: &ApiClient, room_id: &str) -> Result<(), Box<dyn Error>> {
+    loop {
+        print!("Press Enter to start the race once everyone has joined: ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        io::stdin().read_line(&mut line)?;
+
+        match client.start_room(room_id).await {
+            Ok(_) => return Ok(()),
+            Err(e) => println!("Can't start yet: {}", e),
+        }
+    }
+}
+
+async fn wait_until(
+    client: &ApiClient,
+    room_id: &str,
+    done: impl Fn(&RoomView) -> bool,
+) -> Result<RoomView, Box<dyn Error>> {
+    loop {
+        let view = client.race(room_id).await?;
+        if done(&view.room) {
+            return Ok(view.room);
+        }
+        tokio::time::sleep(FRAME_DURATION).await;
+    }
+}
+
+async fn race(client: &ApiClient, room_id: &str) -> Result<RaceView, Box<dyn Error>> {
+    loop {
+        let movement = handle_input(Duration::from_millis(10));
+        let view = client.race_move(room_id, movement).await?;
+
+        let ghosts: Vec<(usize, usize)> = view.ghosts
+            .iter()
+            .filter(|ghost| ghost.alive)
+            .filter_map(|ghost| Some((ghost.column?, ghost.lane)))
+            .collect();
+        render_game_with_ghosts(&view.state, &ghosts);
+
+        if view.state.is_game_over {
+            return Ok(view);
+        }
+        tokio::time::sleep(FRAME_DURATION).await;
+    }
+}
@@ -0,0 +1,93 @@
+use std::error::Error;
+use std::io::{self, stdout, Write};
+use std::time::Duration;
+use crossterm::{
+    execute,
+    terminal::{enable_raw_mode, disable_raw_mode},
+    cursor::{Hide, Show},
+};
+use tracing::{info, warn};
+use crate::client::api::HiScoresResponse;
+use crate::client::ApiClient;
+use crate::core::GameState;
+use crate::ui::{render_game, render_score_breakdown, handle_input, ask_play_again};
+use crate::FRAME_DURATION;
+
+// Plays on a remote server: keystrokes become moves, and every frame sends
+// one move (or "stay") and renders the state the server returns.
+pub async fn run_remote(server_url: &str, token: Option<String>) -> Result<(), Box<dyn Error>> {
+    let mut client = ApiClient::new(server_url);
+    match token {
+        Some(token) => client.set_token(token),
+        None => register(&mut client).await?,
+    }
+
+    loop {
+        let game_id = client.new_game().await?;
+        info!(server = server_url, game_id = %game_id, "remote game started");
+
+        enable_raw_mode()?;
+        execute!(stdout(), Hide)?;
+
+        let result = play(&client, &game_id).await;
+
+        // Restore normal terminal mode even if the connection failed
+        disable_raw_mode()?;
+        execute!(stdout(), Show)?;
+
+        let state = result.inspect_err(|e| warn!(error = %e, "remote game aborted"))?;
+        render_score_breakdown(&state.breakdown);
+
+        let HiScoresResponse { hiscores, placements } = client.submit_score(&game_id).await?;
+        for placement in &placements {
+            println!("You placed #{} on the {} board", placement.rank, placement.board);
+        }
+        if !hiscores.is_empty() {
+            println!("\nHigh Scores:");
+            for (i, entry) in hiscores.iter().enumerate() {
+                let ttl_info = entry.expires_in.map_or(String::new(), |t| format!(" (expires in {}s)", t));
+                println!("{}. {} - {}{}", i + 1, entry.name, entry.score, ttl_info);
+            }
+        }
+
+        println!();
+
+        if !ask_play_again() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn register(client: &mut ApiClient) -> Result<(), Box<dyn Error>> {
+    loop {
+        print!("Enter your name (letters only): ");
+        io::stdout().flush()?;
+
+        let mut name = String::new();
+        io::stdin().read_line(&mut name)?;
+
+        match client.register(name.trim()).await {
+            Ok(registration) => {
+                println!("Registered as {}. Reuse this session with --token {}",
+                    registration.name, registration.token);
+                return Ok(());
+            }
+            Err(e) => println!("Registration failed: {}", e),
+        }
+    }
+}
+
+async fn play(client: &ApiClient, game_id: &str) -> Result<GameState, Box<dyn Error>> {
+    loop {
+        let movement = handle_input(Duration::from_millis(10));
+        let state = client.make_move(game_id, movement).await?;
+        render_game(&state);
+
+        if state.is_game_over {
+            return Ok(state);
+        }
+        tokio::time::sleep(FRAME_DURATION).await;
+    }
+}
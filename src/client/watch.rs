@@ -0,0 +1,51 @@
+use std::io::stdout;
+use crossterm::{execute, cursor::{Hide, Show}};
+use tracing::info;
+use crate::client::ApiClient;
+use crate::core::GameState;
+use crate::ui::render_game;
+
+// Renders a remote game from its Server-Sent Events stream until it ends.
+pub async fn run_watch(server_url: &str, game_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let client = ApiClient::new(server_url);
+    let mut response = client.watch(game_id).await?;
+    info!(server = server_url, game_id = game_id, "watching remote game");
+
+    execute!(stdout(), Hide)?;
+
+    let mut buffer = String::new();
+    let mut last_state = None;
+
+    'stream: while let Some(chunk) = response.chunk().await? {
+        buffer.push_str(&String::from_utf8_lossy(&chunk).replace("\r\n", "\n"));
+
+        // Events are separated by a blank line
+        while let Some(end) = buffer.find("\n\n") {
+            let event: String = buffer.drain(..end + 2).collect();
+            let data: String = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(str::trim_start)
+                .collect();
+
+            if let Ok(state) = serde_json::from_str::<GameState>(&data) {
+                render_game(&state);
+                let is_game_over = state.is_game_over;
+                last_state = Some(state);
+                if is_game_over {
+                    break 'stream;
+                }
+            }
+        }
+    }
+
+    execute!(stdout(), Show)?;
+
+    match last_state {
+        Some(state) if state.is_game_over => {
+            println!("\nGame Over! Final score: {}", state.score);
+        }
+        _ => println!("\nStream ended."),
+    }
+    Ok(())
+}
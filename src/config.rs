@@ -0,0 +1,46 @@
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use crate::logging::LogFormat;
+
+// Optional JSON config file. Every field may be omitted; command-line flags
+// take precedence over values from the file.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub log: LogConfig,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub port: Option<u16>,
+    pub bind: Option<IpAddr>,
+    pub new_games_per_minute: Option<u32>,
+    pub moves_per_second: Option<u32>,
+    pub max_body_bytes: Option<u64>,
+    pub snapshot_interval_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: Option<String>,
+    pub format: Option<LogFormat>,
+    pub file: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
+        let config: Config = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        if config.server.snapshot_interval_secs == Some(0) {
+            return Err(format!("Invalid config {}: snapshot_interval_secs must be at least 1", path.display()));
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use serde::Serialize;
+use tracing::{error, info};
+use super::events::{GameEvent, Subscriber};
+use super::game::GameState;
+use super::profile::ticks_to_secs;
+use crate::storage::{self, Store};
+
+const ACHIEVEMENT_PREFIX: &str = "achievements:";
+
+// What has happened so far in the current run
+#[derive(Default)]
+struct RunProgress {
+    score: u32,
+    pickups: u32,
+    // Ticks since the player last changed lanes
+    lane_ticks: u32,
+}
+
+pub enum Goal {
+    Score(u32),
+    PickupsInRun(u32),
+    SecondsInOneLane(u32),
+}
+
+impl Goal {
+    fn is_met(&self, progress: &RunProgress) -> bool {
+        match *self {
+            Goal::Score(score) => progress.score >= score,
+            Goal::PickupsInRun(pickups) => progress.pickups >= pickups,
+            Goal::SecondsInOneLane(secs) => ticks_to_secs(progress.lane_ticks) >= f64::from(secs),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Achievement {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    #[serde(skip)]
+    pub goal: Goal,
+}
+
+// Ids are stored per player, so never rename one
+pub const ACHIEVEMENTS: &[Achievement] = &[
+    Achievement {
+        id: "score-100",
+        name: "Warming Up",
+        description: "Reach a score of 100",
+        goal: Goal::Score(100),
+    },
+    Achievement {
+        id: "score-500",
+        name: "Marathon",
+        description: "Reach a score of 500",
+        goal: Goal::Score(500),
+    },
+    Achievement {
+        id: "steady-hand",
+        name: "Steady Hand",
+        description: "Survive 60 seconds without changing lanes",
+        goal: Goal::SecondsInOneLane(60),
+    },
+    Achievement {
+        id: "collector",
+        name: "Collector",
+        description: "Collect 10 coins in one run",
+        goal: Goal::PickupsInRun(10),
+    },
+];
+
+impl Achievement {
+    pub fn by_id(id: &str) -> Option<&'static Achievement> {
+        ACHIEVEMENTS.iter().find(|achievement| achievement.id == id)
+    }
+}
+
+// Follows a game's events and notes each achievement the first time the run
+// reaches its goal
+#[derive(Default)]
+pub struct AchievementTracker {
+    progress: RunProgress,
+    reached: Vec<&'static str>,
+    pending: Vec<&'static Achievement>,
+}
+
+impl AchievementTracker {
+    // Carries on from a restored game. How long the player had been in their
+    // lane isn't saved, so that streak starts over.
+    pub fn resume(state: &GameState) -> Self {
+        Self {
+            progress: RunProgress { score: state.score, pickups: state.pickups, lane_ticks: 0 },
+            reached: state.achievements.iter().filter_map(|id| Achievement::by_id(id)).map(|a| a.id).collect(),
+            pending: Vec::new(),
+        }
+    }
+
+    // Achievements reached since the last call
+    pub fn take_reached(&mut self) -> Vec<&'static Achievement> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+impl Subscriber for AchievementTracker {
+    fn on_event(&mut self, event: &GameEvent) {
+        match *event {
+            GameEvent::Tick { score, .. } => {
+                self.progress.score = score;
+                self.progress.lane_ticks += 1;
+            }
+            GameEvent::LaneChanged { .. } => self.progress.lane_ticks = 0,
+            GameEvent::PickupCollected { total, .. } => self.progress.pickups = total,
+            _ => return,
+        }
+
+        for achievement in ACHIEVEMENTS {
+            if achievement.goal.is_met(&self.progress) && !self.reached.contains(&achievement.id) {
+                self.reached.push(achievement.id);
+                self.pending.push(achievement);
+            }
+        }
+    }
+}
+
+pub struct AchievementManager {
+    store: Store,
+}
+
+impl AchievementManager {
+    pub fn new(store: Store) -> Self {
+        Self { store }
+    }
+
+    // Achievement ids a player has earned, with the time each was first earned
+    pub fn earned(&self, player_id: &str) -> BTreeMap<String, u64> {
+        self.store.get(&format!("{}{}", ACHIEVEMENT_PREFIX, player_id))
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    // Adds the achievements reached in a run and returns the ones the player
+    // didn't have yet
+    pub fn record(&self, player_id: &str, reached: &[String]) -> Vec<&'static Achievement> {
+        let key = format!("{}{}", ACHIEVEMENT_PREFIX, player_id);
+        let _lock = storage::lock_key(&key);
+        let mut earned = self.earned(player_id);
+        let now = storage::now();
+        let new: Vec<&'static Achievement> = reached
+            .iter()
+            .filter_map(|id| Achievement::by_id(id))
+            .filter(|achievement| {
+                if earned.contains_key(achievement.id) {
+                    return false;
+                }
+                earned.insert(achievement.id.to_string(), now);
+                true
+            })
+            .collect();
+        if new.is_empty() {
+            return new;
+        }
+
+        let json = serde_json::to_string(&earned).unwrap();
+        match self.store.set(&key, &json, None) {
+            Ok(_) => info!(player_id, unlocked = new.len(), "achievements unlocked"),
+            Err(e) => error!(player_id, error = %e, "failed to save achievements"),
+        }
+        new
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use crate::storage::MemoryStorage;
+    use super::*;
+
+    #[test]
+    fn collecting_enough_pickups_reaches_collector() {
+        let mut tracker = AchievementTracker::default();
+        for total in 1..=10 {
+            assert!(tracker.take_reached().is_empty());
+            tracker.on_event(&GameEvent::PickupCollected { tick: total, lane: 0, total });
+        }
+
+        let reached: Vec<&str> = tracker.take_reached().iter().map(|achievement| achievement.id).collect();
+        assert_eq!(reached, ["collector"]);
+    }
+
+    #[test]
+    fn concurrent_unlocks_are_all_kept() {
+        let store: Store = Arc::new(MemoryStorage::new());
+
+        let threads: Vec<_> = ACHIEVEMENTS
+            .iter()
+            .map(|achievement| {
+                let store = store.clone();
+                let reached = vec![achievement.id.to_string()];
+                thread::spawn(move || AchievementManager::new(store).record("ann", &reached))
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap().len(), 1);
+        }
+
+        assert_eq!(AchievementManager::new(store).earned("ann").len(), ACHIEVEMENTS.len());
+    }
+}
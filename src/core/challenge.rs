@@ -0,0 +1,103 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use tracing::info;
+use super::leaderboard::{format_date, DAY};
+use super::player::Player;
+use super::score::ScoreManager;
+use super::scoring::ScoreBreakdown;
+use crate::storage::{self, Store};
+
+const ATTEMPT_PREFIX: &str = "challenge_attempt:";
+
+// The course everyone plays on one UTC date
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Challenge {
+    pub date: String,
+    pub seed: u64,
+    // Next midnight UTC, when the challenge and its board close
+    pub ends_at: u64,
+}
+
+impl Challenge {
+    pub fn today() -> Self {
+        Self::on(storage::now())
+    }
+
+    pub fn on(now: u64) -> Self {
+        let day = now / DAY;
+        Challenge {
+            date: format_date(now),
+            seed: seed_for_day(day),
+            ends_at: (day + 1) * DAY,
+        }
+    }
+
+    // Seconds until the challenge closes. A game that finishes after
+    // midnight still gets its result onto the board for a moment.
+    pub fn ttl(&self) -> u64 {
+        self.ends_at.saturating_sub(storage::now()).max(1)
+    }
+}
+
+// SplitMix64 of the day number. Unlike std's hashers its output is fixed, so
+// every build and platform agrees on the day's course.
+fn seed_for_day(day: u64) -> u64 {
+    let mut z = day.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[derive(Debug)]
+pub enum ChallengeError {
+    AlreadyPlayed,
+    Store(String),
+}
+
+impl fmt::Display for ChallengeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChallengeError::AlreadyPlayed => write!(f, "Today's challenge has already been played"),
+            ChallengeError::Store(e) => write!(f, "Store error: {}", e),
+        }
+    }
+}
+
+pub struct ChallengeManager {
+    store: Store,
+    scores: ScoreManager,
+}
+
+impl ChallengeManager {
+    pub fn new(store: Store) -> Self {
+        Self {
+            scores: ScoreManager::new(store.clone()),
+            store,
+        }
+    }
+
+    // Claims the player's one attempt. The claim is taken when the game
+    // starts, so quitting or crashing still uses it up.
+    pub fn start(&self, player_id: &str, challenge: &Challenge) -> Result<(), ChallengeError> {
+        let key = format!("{}{}:{}", ATTEMPT_PREFIX, challenge.date, player_id);
+        let claimed = self.store
+            .insert_new(&key, &storage::now().to_string(), Some(challenge.ttl()))
+            .map_err(|e| ChallengeError::Store(e.to_string()))?;
+        if !claimed {
+            return Err(ChallengeError::AlreadyPlayed);
+        }
+        info!(player_id, date = %challenge.date, "daily challenge started");
+        Ok(())
+    }
+
+    // Puts a finished attempt on the challenge board and returns its rank
+    pub fn finish(&self, player: &Player, challenge: &Challenge, breakdown: &ScoreBreakdown) -> usize {
+        let rank = self.scores.submit_challenge(player, challenge, breakdown);
+        info!(player_id = %player.id, date = %challenge.date, score = breakdown.total(), rank, "daily challenge finished");
+        rank
+    }
+
+    pub fn standings(&self, challenge: &Challenge) -> Vec<(String, u32, Option<u64>)> {
+        self.scores.challenge_scores(challenge)
+    }
+}
@@ -0,0 +1,31 @@
+use serde::Serialize;
+
+// Something that happened in a game. Lanes are 0 (top) and 1 (bottom), and
+// `tick` is the game tick the event happened on.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GameEvent {
+    // The course scrolled one column and the score went up
+    Tick { tick: u32, score: u32 },
+    ObstacleSpawned { tick: u32, lane: usize },
+    LaneChanged { tick: u32, from: usize, to: usize },
+    // An obstacle in `lane`, next to the player's, came within one column of
+    // them. `points` is the bonus scored with the combo's multiplier.
+    NearMiss { tick: u32, lane: usize, combo: u32, points: u32 },
+    // `total` is the number of pickups collected so far this run
+    PickupCollected { tick: u32, lane: usize, total: u32 },
+    Collision { tick: u32, lane: usize },
+    GameOver { tick: u32, score: u32, quit: bool },
+}
+
+// Receives every event of the games it is subscribed to, in order. Called on
+// the thread driving the game, so keep it cheap.
+pub trait Subscriber: Send {
+    fn on_event(&mut self, event: &GameEvent);
+}
+
+impl<F: FnMut(&GameEvent) + Send> Subscriber for F {
+    fn on_event(&mut self, event: &GameEvent) {
+        self(event)
+    }
+}
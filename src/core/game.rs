@@ -1,11 +1,20 @@
-use std::sync::{Arc, Mutex};
-use rand::Rng;
-use simple_kv_store::KvStore;
-use super::score::ScoreManager;
-use crate::{GAME_WIDTH, OBSTACLE_CHANCE, INITIAL_OBSTACLE_DENSITY};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use super::achievements::AchievementTracker;
+use super::events::{GameEvent, Subscriber};
+use super::player::Player;
+use super::score::{ScoreManager, ScoreOutcome};
+use super::scoring::{Combo, ScoreBreakdown};
+use crate::storage::Store;
+use crate::{GAME_WIDTH, OBSTACLE_CHANCE, INITIAL_OBSTACLE_DENSITY, PICKUP_CHANCE};
 use serde::{Serialize, Deserialize};
+use tracing::debug;
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+// Pickups are drawn from their own stream so adding them left the obstacle
+// course of every existing seed unchanged
+const PICKUP_SEED_SALT: u64 = 0x7069_636b_7570_7321;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum PlayerMove {
     Up,
     Down,
@@ -19,18 +28,51 @@ pub struct GameState {
     pub bottom_row: Vec<bool>,
     pub score: u32,
     pub is_game_over: bool,
+    // Coins to collect in the top and bottom lane, one entry per column.
+    // They only count towards stats and achievements.
+    #[serde(default)]
+    pub top_pickups: Vec<bool>,
+    #[serde(default)]
+    pub bottom_pickups: Vec<bool>,
+    // Pickups collected this run
+    #[serde(default)]
+    pub pickups: u32,
+    // Obstacles hit this run in the top and bottom lane
+    #[serde(default)]
+    pub collisions: [u32; 2],
+    // Ids of the achievements reached this run, in the order they were reached
+    #[serde(default)]
+    pub achievements: Vec<String>,
+    #[serde(default)]
+    pub breakdown: ScoreBreakdown,
+    #[serde(default)]
+    pub combo: Combo,
 }
 
 pub struct Game {
     state: GameState,
-    store: Arc<Mutex<KvStore>>,
+    store: Store,
     score_manager: ScoreManager,
+    rng: StdRng,
+    pickup_rng: StdRng,
+    seed: u64,
+    tick: u32,
+    // Course position (tick plus column) of the last obstacle scored as a
+    // near miss in each lane
+    near_missed: [u32; 2],
+    achievements: AchievementTracker,
+    subscribers: Vec<Box<dyn Subscriber>>,
 }
 
 impl Game {
-    pub fn new(store: Arc<Mutex<KvStore>>) -> Self {
-        let mut rng = rand::thread_rng();
-        
+    pub fn new(store: Store) -> Self {
+        Self::with_seed(store, rand::thread_rng().gen())
+    }
+
+    // Games created with the same seed get the same obstacle course.
+    pub fn with_seed(store: Store, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+
         let top_row = (0..GAME_WIDTH)
             .map(|i| {
                 if i <= 10 { false } 
@@ -52,40 +94,178 @@ impl Game {
                 bottom_row,
                 score: 0,
                 is_game_over: false,
+                top_pickups: vec![false; GAME_WIDTH],
+                bottom_pickups: vec![false; GAME_WIDTH],
+                pickups: 0,
+                collisions: [0; 2],
+                achievements: Vec::new(),
+                breakdown: ScoreBreakdown::default(),
+                combo: Combo::default(),
             },
             store: store.clone(),
             score_manager: ScoreManager::new(store),
+            rng,
+            pickup_rng: StdRng::seed_from_u64(seed ^ PICKUP_SEED_SALT),
+            seed,
+            tick: 0,
+            near_missed: [0; 2],
+            achievements: AchievementTracker::default(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    // Rebuilds a game saved at `tick`. The course is regenerated from the
+    // seed so later obstacles match what the original game would have spawned.
+    pub fn restore(store: Store, seed: u64, tick: u32, state: GameState) -> Self {
+        let mut game = Self::with_seed(store, seed);
+        for _ in 0..tick {
+            game.advance_course();
         }
+        // Snapshots taken before pickups existed keep the regenerated ones
+        let (top_pickups, bottom_pickups) = if state.top_pickups.len() == GAME_WIDTH {
+            (state.top_pickups.clone(), state.bottom_pickups.clone())
+        } else {
+            (game.state.top_pickups.clone(), game.state.bottom_pickups.clone())
+        };
+        let mut breakdown = state.breakdown.clone();
+        // Snapshots taken before near misses were scored have only distance
+        if breakdown.total() == 0 {
+            breakdown.distance = state.score;
+        }
+        game.state = GameState { top_pickups, bottom_pickups, breakdown, ..state };
+        game.tick = tick;
+        // Anything already in range was scored before the game was saved
+        game.near_missed = [tick + game.state.player_pos.0 as u32 + 1; 2];
+        game.achievements = AchievementTracker::resume(&game.state);
+        game
     }
 
     pub fn get_state(&self) -> GameState {
         self.state.clone()
     }
 
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    // Subscribers see every event from now on, after the game's own
+    // achievement tracking
+    pub fn subscribe(&mut self, subscriber: Box<dyn Subscriber>) {
+        self.subscribers.push(subscriber);
+    }
+
+    fn emit(&mut self, event: GameEvent) {
+        self.achievements.on_event(&event);
+        for subscriber in &mut self.subscribers {
+            subscriber.on_event(&event);
+        }
+
+        for achievement in self.achievements.take_reached() {
+            debug!(tick = self.tick, achievement = achievement.id, "achievement reached");
+            self.state.achievements.push(achievement.id.to_string());
+        }
+    }
+
     pub fn update(&mut self) {
         if self.state.is_game_over {
             return;
         }
 
-        self.state.score += 1;
+        self.tick += 1;
+        self.state.breakdown.distance += 1;
+        self.state.score = self.state.breakdown.total();
+        self.state.combo.tick();
+        self.emit(GameEvent::Tick { tick: self.tick, score: self.state.score });
+        for lane in self.advance_course() {
+            self.emit(GameEvent::ObstacleSpawned { tick: self.tick, lane });
+        }
+
+        let (x, lane) = self.state.player_pos;
+        if self.is_collision() {
+            debug!(tick = self.tick, score = self.state.score, "collision");
+            self.state.collisions[lane] += 1;
+            self.state.is_game_over = true;
+            self.emit(GameEvent::Collision { tick: self.tick, lane });
+        } else if self.pickups_mut(lane)[x] {
+            self.pickups_mut(lane)[x] = false;
+            self.state.pickups += 1;
+            debug!(tick = self.tick, pickups = self.state.pickups, "pickup collected");
+            self.emit(GameEvent::PickupCollected { tick: self.tick, lane, total: self.state.pickups });
+        }
+
+        if !self.state.is_game_over {
+            self.score_near_misses();
+        }
+
+        if self.state.is_game_over {
+            self.emit(GameEvent::GameOver { tick: self.tick, score: self.state.score, quit: false });
+        }
+    }
+
+    // An obstacle in the other lane within one column of the player is a
+    // near miss. Obstacles scroll one column a tick, so tick plus column
+    // names the same obstacle for as long as it stays in range.
+    fn score_near_misses(&mut self) {
+        let (x, lane) = self.state.player_pos;
+        let other = 1 - lane;
+        for column in x.saturating_sub(1)..=(x + 1).min(GAME_WIDTH - 1) {
+            let obstacle = self.tick + column as u32;
+            if !self.lane(other)[column] || obstacle <= self.near_missed[other] {
+                continue;
+            }
+            self.near_missed[other] = obstacle;
+
+            let points = self.state.combo.near_miss(&mut self.state.breakdown);
+            self.state.score = self.state.breakdown.total();
+            debug!(tick = self.tick, combo = self.state.combo.count, points, "near miss");
+            self.emit(GameEvent::NearMiss { tick: self.tick, lane: other, combo: self.state.combo.count, points });
+        }
+    }
+
+    fn lane(&self, lane: usize) -> &[bool] {
+        if lane == 0 { &self.state.top_row } else { &self.state.bottom_row }
+    }
+
+    fn pickups_mut(&mut self, lane: usize) -> &mut Vec<bool> {
+        if lane == 0 { &mut self.state.top_pickups } else { &mut self.state.bottom_pickups }
+    }
+
+    // Scrolls both lanes one column and spawns the next column of obstacles.
+    // Returns the lanes an obstacle was spawned in.
+    fn advance_course(&mut self) -> Vec<usize> {
         self.state.top_row.rotate_left(1);
         self.state.bottom_row.rotate_left(1);
 
-        let mut rng = rand::thread_rng();
-        self.state.top_row[GAME_WIDTH - 1] = rng.gen_bool(OBSTACLE_CHANCE);
-        self.state.bottom_row[GAME_WIDTH - 1] = rng.gen_bool(OBSTACLE_CHANCE);
+        self.state.top_row[GAME_WIDTH - 1] = self.rng.gen_bool(OBSTACLE_CHANCE);
+        self.state.bottom_row[GAME_WIDTH - 1] = self.rng.gen_bool(OBSTACLE_CHANCE);
 
         if self.state.top_row[GAME_WIDTH - 1] && self.state.bottom_row[GAME_WIDTH - 1] {
-            if rng.gen_bool(0.5) {
+            if self.rng.gen_bool(0.5) {
                 self.state.top_row[GAME_WIDTH - 1] = false;
             } else {
                 self.state.bottom_row[GAME_WIDTH - 1] = false;
             }
         }
 
-        if self.is_collision() {
-            self.state.is_game_over = true;
-        }
+        // Pickups only appear in free cells. Both lanes draw every tick so
+        // the pickup stream stays aligned with the course.
+        self.state.top_pickups.rotate_left(1);
+        self.state.bottom_pickups.rotate_left(1);
+        let top_pickup = self.pickup_rng.gen_bool(PICKUP_CHANCE);
+        let bottom_pickup = self.pickup_rng.gen_bool(PICKUP_CHANCE);
+        self.state.top_pickups[GAME_WIDTH - 1] = top_pickup && !self.state.top_row[GAME_WIDTH - 1];
+        self.state.bottom_pickups[GAME_WIDTH - 1] = bottom_pickup && !self.state.bottom_row[GAME_WIDTH - 1];
+
+        [&self.state.top_row, &self.state.bottom_row]
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row[GAME_WIDTH - 1])
+            .map(|(lane, _)| lane)
+            .collect()
     }
 
     pub fn handle_input(&mut self, movement: PlayerMove) {
@@ -93,11 +273,20 @@ impl Game {
             return;
         }
 
+        let lane = self.state.player_pos.1;
         match movement {
             PlayerMove::Up => self.state.player_pos.1 = 0,
             PlayerMove::Down => self.state.player_pos.1 = 1,
             PlayerMove::Quit => self.state.is_game_over = true,
         }
+
+        let to = self.state.player_pos.1;
+        if to != lane {
+            self.emit(GameEvent::LaneChanged { tick: self.tick, from: lane, to });
+        }
+        if self.state.is_game_over {
+            self.emit(GameEvent::GameOver { tick: self.tick, score: self.state.score, quit: true });
+        }
     }
 
     fn is_collision(&self) -> bool {
@@ -105,11 +294,51 @@ impl Game {
         (y == 0 && self.state.top_row[x]) || (y == 1 && self.state.bottom_row[x])
     }
 
-    pub fn handle_game_over(&self) -> Vec<(String, u32, Option<u64>)> {
+    pub fn handle_game_over(&self, player: &Player) -> ScoreOutcome {
         if !self.state.is_game_over {
-            return vec![];
+            return ScoreOutcome { placements: vec![], hiscores: vec![] };
         }
         
-        self.score_manager.handle_new_score(self.state.score)
+        self.score_manager.handle_new_score(player, &self.state.breakdown)
     }
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::storage::MemoryStorage;
+    use super::*;
+
+    #[test]
+    fn an_obstacle_in_the_next_lane_is_one_near_miss() {
+        let mut game = Game::with_seed(Arc::new(MemoryStorage::new()), 1);
+        // The player starts at column 1 of the bottom lane
+        game.state.top_row = vec![false; GAME_WIDTH];
+        game.state.bottom_row = vec![false; GAME_WIDTH];
+        game.state.top_row[4] = true;
+
+        // It reaches column 2 on the second tick and stays in range through
+        // the fourth, then scrolls out
+        for _ in 0..6 {
+            game.update();
+        }
+
+        assert!(!game.state.is_game_over);
+        assert_eq!(game.state.breakdown.near_misses, 1);
+        assert_eq!(game.state.score, 6 + crate::core::scoring::NEAR_MISS_BONUS);
+    }
+
+    #[test]
+    fn collecting_a_pickup_leaves_the_score_alone() {
+        let mut game = Game::with_seed(Arc::new(MemoryStorage::new()), 1);
+        game.state.top_row = vec![false; GAME_WIDTH];
+        game.state.bottom_row = vec![false; GAME_WIDTH];
+        game.state.bottom_pickups[2] = true;
+
+        game.update();
+
+        assert_eq!(game.state.pickups, 1);
+        assert!(!game.state.bottom_pickups[1]);
+        assert_eq!(game.state.score, 1);
+    }
+}
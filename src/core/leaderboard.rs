@@ -0,0 +1,93 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+
+pub const DAY: u64 = 24 * 60 * 60;
+const WEEK: u64 = 7 * DAY;
+
+// Leaderboards kept side by side. Each board stores its own copy of a
+// qualifying score under its own key prefix and is pruned to the
+// configured size independently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Board {
+    // Never expires
+    AllTime,
+    // Resets at midnight UTC
+    Daily,
+    // Resets at midnight UTC between Sunday and Monday
+    Weekly,
+    // Each entry expires `hiscore_ttl` seconds after it was set
+    Rolling,
+}
+
+impl Board {
+    pub const ALL: [Board; 4] = [Board::AllTime, Board::Daily, Board::Weekly, Board::Rolling];
+
+    // Board shown when the caller doesn't ask for a specific one
+    pub const DEFAULT: Board = Board::Rolling;
+
+    // Key segment that identifies the board, and for daily and weekly boards
+    // the period `now` falls in, e.g. "day-2024-05-01:"
+    pub fn key_segment(&self, now: u64) -> String {
+        match self {
+            Board::AllTime => "all:".to_string(),
+            Board::Daily => format!("day-{}:", format_date(now)),
+            Board::Weekly => format!("week-{}:", format_date(week_start(now))),
+            Board::Rolling => "rolling:".to_string(),
+        }
+    }
+
+    // How long an entry set at `now` stays on the board
+    pub fn ttl(&self, now: u64, rolling_ttl: u64) -> Option<u64> {
+        match self {
+            Board::AllTime => None,
+            Board::Daily => Some(DAY - now % DAY),
+            Board::Weekly => Some(week_start(now) + WEEK - now),
+            Board::Rolling => Some(rolling_ttl),
+        }
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Board::AllTime => "all-time",
+            Board::Daily => "daily",
+            Board::Weekly => "weekly",
+            Board::Rolling => "rolling",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Board {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Board::ALL
+            .into_iter()
+            .find(|board| board.to_string() == s.to_lowercase())
+            .ok_or_else(|| format!("Unknown board \"{}\" (expected all-time, daily, weekly or rolling)", s))
+    }
+}
+
+// Start of the Monday-based week containing `now`. 1970-01-01 was a Thursday.
+fn week_start(now: u64) -> u64 {
+    let days = now / DAY;
+    days.saturating_sub((days + 3) % 7) * DAY
+}
+
+// Formats the UTC date of a Unix timestamp as YYYY-MM-DD
+pub fn format_date(timestamp: u64) -> String {
+    let z = (timestamp / DAY) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
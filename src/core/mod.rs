@@ -1,5 +1,21 @@
+mod achievements;
+mod challenge;
+mod events;
 mod game;
+mod leaderboard;
+mod player;
+mod profile;
+mod replay;
 mod score;
+mod scoring;
 
+pub use achievements::{Achievement, AchievementManager, ACHIEVEMENTS};
+pub use challenge::{Challenge, ChallengeError, ChallengeManager};
+pub use events::{GameEvent, Subscriber};
 pub use game::{Game, GameState, PlayerMove};
-pub use score::ScoreManager; 
\ No newline at end of file
+pub use leaderboard::{format_date, Board};
+pub use player::{Player, PlayerError, PlayerManager};
+pub use profile::{ticks_to_secs, Profile, ProfileManager};
+pub use replay::{Replay, ReplayManager};
+pub use score::{Placement, ScoreManager, BOARDS_KEY, BOARD_SIZE_KEY};
+pub use scoring::ScoreBreakdown;
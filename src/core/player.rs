@@ -0,0 +1,180 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use crate::storage::{Storage, Store};
+
+const PLAYER_PREFIX: &str = "player:";
+const PLAYER_NAME_PREFIX: &str = "player_name:";
+const TOKEN_PREFIX: &str = "token:";
+pub const MAX_NAME_LENGTH: usize = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Player {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PlayerRef {
+    player_id: String,
+    // Set on token entries keyed by the token's digest. Tokens issued before
+    // they were hashed are keyed by the token itself until next used.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    hashed: bool,
+}
+
+#[derive(Debug)]
+pub enum PlayerError {
+    InvalidName,
+    NameTaken,
+    Store(String),
+}
+
+impl fmt::Display for PlayerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerError::InvalidName => write!(
+                f,
+                "Name must be 1-{} letters (a-z, A-Z)",
+                MAX_NAME_LENGTH
+            ),
+            PlayerError::NameTaken => write!(f, "Name is already registered"),
+            PlayerError::Store(e) => write!(f, "Store error: {}", e),
+        }
+    }
+}
+
+pub struct PlayerManager {
+    store: Store,
+}
+
+impl PlayerManager {
+    pub fn new(store: Store) -> Self {
+        Self { store }
+    }
+
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= MAX_NAME_LENGTH
+            && name.chars().all(|c| c.is_ascii_alphabetic())
+    }
+
+    fn generate_token() -> String {
+        format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+    }
+
+    // Only a digest of each token is stored, so reading the store doesn't
+    // hand out working tokens
+    fn token_key(token: &str) -> String {
+        format!("{}{:x}", TOKEN_PREFIX, Sha256::digest(token.as_bytes()))
+    }
+
+    fn load<T: DeserializeOwned>(store: &dyn Storage, key: &str) -> Option<T> {
+        store.get(key).and_then(|data| serde_json::from_str(&data).ok())
+    }
+
+    fn save<T: Serialize>(store: &dyn Storage, key: String, value: &T) -> Result<(), PlayerError> {
+        let json = serde_json::to_string(value).unwrap();
+        store.set(&key, &json, None)
+            .map_err(|e| PlayerError::Store(e.to_string()))
+    }
+
+    // Registers a new player and returns it together with its API token.
+    pub fn register(&self, name: &str) -> Result<(Player, String), PlayerError> {
+        if !Self::is_valid_name(name) {
+            return Err(PlayerError::InvalidName);
+        }
+
+        let player = Player {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+        };
+        let token = Self::generate_token();
+        let player_ref = PlayerRef { player_id: player.id.clone(), hashed: false };
+
+        // Claiming the name first means two concurrent registrations of the
+        // same name can't both succeed
+        let name_key = format!("{}{}", PLAYER_NAME_PREFIX, name.to_lowercase());
+        let claimed = self.store
+            .insert_new(&name_key, &serde_json::to_string(&player_ref).unwrap(), None)
+            .map_err(|e| PlayerError::Store(e.to_string()))?;
+        if !claimed {
+            return Err(PlayerError::NameTaken);
+        }
+
+        let store = self.store.as_ref();
+        Self::save(store, format!("{}{}", PLAYER_PREFIX, player.id), &player)?;
+        let token_ref = PlayerRef { hashed: true, ..player_ref };
+        Self::save(store, Self::token_key(&token), &token_ref)?;
+
+        Ok((player, token))
+    }
+
+    pub fn authenticate(&self, token: &str) -> Option<Player> {
+        let store = self.store.as_ref();
+        let player_ref = match Self::load::<PlayerRef>(store, &Self::token_key(token)) {
+            Some(player_ref) => player_ref,
+            None => self.upgrade_token(token)?,
+        };
+        Self::load(store, &format!("{}{}", PLAYER_PREFIX, player_ref.player_id))
+    }
+
+    // Moves a token stored in plain text to its digest key. A hashed entry
+    // never matches here, or its stored digest would work as a token.
+    fn upgrade_token(&self, token: &str) -> Option<PlayerRef> {
+        let store = self.store.as_ref();
+        let plain_key = format!("{}{}", TOKEN_PREFIX, token);
+        let player_ref: PlayerRef = Self::load(store, &plain_key).filter(|r: &PlayerRef| !r.hashed)?;
+
+        let upgraded = PlayerRef { hashed: true, ..player_ref };
+        if Self::save(store, Self::token_key(token), &upgraded).is_ok() {
+            let _ = store.delete(&plain_key);
+        }
+        Some(upgraded)
+    }
+
+    pub fn find_by_name(&self, name: &str) -> Option<Player> {
+        let store = self.store.as_ref();
+        let player_ref: PlayerRef =
+            Self::load(store, &format!("{}{}", PLAYER_NAME_PREFIX, name.to_lowercase()))?;
+        Self::load(store, &format!("{}{}", PLAYER_PREFIX, player_ref.player_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::storage::MemoryStorage;
+    use super::*;
+
+    #[test]
+    fn tokens_are_stored_as_digests() {
+        let store: Store = Arc::new(MemoryStorage::new());
+        let players = PlayerManager::new(store.clone());
+        let (player, token) = players.register("ann").unwrap();
+
+        let keys: Vec<String> = store.scan(TOKEN_PREFIX).into_iter().map(|entry| entry.key).collect();
+        assert_eq!(keys, [PlayerManager::token_key(&token)]);
+        assert_eq!(players.authenticate(&token).unwrap().id, player.id);
+
+        // The stored digest is not a token
+        let digest = keys[0].strip_prefix(TOKEN_PREFIX).unwrap();
+        assert!(players.authenticate(digest).is_none());
+    }
+
+    #[test]
+    fn plain_text_tokens_are_hashed_on_first_use() {
+        let store: Store = Arc::new(MemoryStorage::new());
+        let players = PlayerManager::new(store.clone());
+        let (player, _) = players.register("bob").unwrap();
+        let plain_key = format!("{}legacy", TOKEN_PREFIX);
+        store.set(&plain_key, &format!(r#"{{"player_id":"{}"}}"#, player.id), None).unwrap();
+
+        assert_eq!(players.authenticate("legacy").unwrap().id, player.id);
+        assert!(store.get(&plain_key).is_none());
+        assert!(store.get(&PlayerManager::token_key("legacy")).is_some());
+        assert_eq!(players.authenticate("legacy").unwrap().id, player.id);
+    }
+}
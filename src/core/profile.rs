@@ -0,0 +1,207 @@
+use serde::{Serialize, Deserialize};
+use tracing::{error, info};
+use super::game::GameState;
+use super::replay::ReplayManager;
+use crate::storage::{self, Store};
+use crate::FRAME_DURATION;
+
+const PROFILE_PREFIX: &str = "profile:";
+
+// The highest-scoring game a player has finished
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BestRun {
+    pub score: u32,
+    pub ticks: u32,
+    #[serde(default)]
+    pub pickups: u32,
+    pub recorded_at: u64,
+    // Only terminal games are recorded as replays
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay_id: Option<String>,
+}
+
+// Lifetime statistics, updated once per finished game
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Profile {
+    pub games_played: u32,
+    pub total_score: u64,
+    pub best_run: Option<BestRun>,
+    pub longest_survival_ticks: u32,
+    // Obstacles hit in the top and bottom lane
+    pub collisions: [u32; 2],
+    // Power-ups picked up over every game
+    #[serde(default)]
+    pub pickups_collected: u32,
+}
+
+impl Profile {
+    pub fn average_score(&self) -> f64 {
+        if self.games_played == 0 {
+            0.0
+        } else {
+            self.total_score as f64 / self.games_played as f64
+        }
+    }
+
+    pub fn longest_survival_secs(&self) -> f64 {
+        ticks_to_secs(self.longest_survival_ticks)
+    }
+
+    fn add_run(&mut self, state: &GameState, ticks: u32, replay_id: Option<&str>) {
+        self.games_played += 1;
+        self.total_score += u64::from(state.score);
+        self.longest_survival_ticks = self.longest_survival_ticks.max(ticks);
+        self.collisions[0] += state.collisions[0];
+        self.collisions[1] += state.collisions[1];
+        self.pickups_collected += state.pickups;
+
+        if self.best_run.as_ref().is_none_or(|best| state.score > best.score) {
+            self.best_run = Some(BestRun {
+                score: state.score,
+                ticks,
+                pickups: state.pickups,
+                recorded_at: storage::now(),
+                replay_id: replay_id.map(str::to_string),
+            });
+        }
+    }
+}
+
+fn best_replay(profile: &Profile) -> Option<String> {
+    profile.best_run.as_ref().and_then(|best| best.replay_id.clone())
+}
+
+// Each tick is one frame in terminal mode and one move on the server
+pub fn ticks_to_secs(ticks: u32) -> f64 {
+    f64::from(ticks) * FRAME_DURATION.as_secs_f64()
+}
+
+pub struct ProfileManager {
+    store: Store,
+}
+
+impl ProfileManager {
+    pub fn new(store: Store) -> Self {
+        Self { store }
+    }
+
+    // A player who hasn't finished a game yet has an empty profile. A best
+    // run whose replay has expired no longer points at it.
+    pub fn get(&self, player_id: &str) -> Profile {
+        let mut profile: Profile = self.store.get(&format!("{}{}", PROFILE_PREFIX, player_id))
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default();
+        if let Some(best) = &mut profile.best_run {
+            let replays = ReplayManager::new(self.store.clone());
+            best.replay_id = best.replay_id.take().filter(|id| replays.get(id).is_some());
+        }
+        profile
+    }
+
+    pub fn record_run(&self, player_id: &str, state: &GameState, ticks: u32, replay_id: Option<&str>) -> Profile {
+        let key = format!("{}{}", PROFILE_PREFIX, player_id);
+        let _lock = storage::lock_key(&key);
+        let mut profile = self.get(player_id);
+        let previous_replay = best_replay(&profile);
+        profile.add_run(state, ticks, replay_id);
+
+        let json = serde_json::to_string(&profile).unwrap();
+        match self.store.set(&key, &json, None) {
+            Ok(_) => info!(player_id, games_played = profile.games_played, "profile updated"),
+            Err(e) => {
+                error!(player_id, error = %e, "failed to update profile");
+                return profile;
+            }
+        }
+
+        let best = best_replay(&profile);
+        if best != previous_replay {
+            let replays = ReplayManager::new(self.store.clone());
+            if let Some(id) = &best {
+                replays.set_kept(id, true);
+            }
+            if let Some(id) = &previous_replay {
+                replays.set_kept(id, false);
+            }
+        }
+        profile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use crate::core::Game;
+    use crate::storage::MemoryStorage;
+    use super::*;
+
+    #[test]
+    fn every_run_adds_to_the_lifetime_stats() {
+        let store: Store = Arc::new(MemoryStorage::new());
+        let mut state = Game::with_seed(store.clone(), 1).get_state();
+        let profiles = ProfileManager::new(store);
+
+        state.score = 40;
+        state.pickups = 3;
+        state.collisions = [1, 0];
+        profiles.record_run("ann", &state, 40, None);
+        state.score = 20;
+        state.pickups = 2;
+        state.collisions = [0, 1];
+        let profile = profiles.record_run("ann", &state, 20, None);
+
+        assert_eq!(profile.games_played, 2);
+        assert_eq!(profile.total_score, 60);
+        assert_eq!(profile.longest_survival_ticks, 40);
+        assert_eq!(profile.collisions, [1, 1]);
+        assert_eq!(profile.pickups_collected, 5);
+        assert_eq!(profile.best_run.unwrap().pickups, 3);
+    }
+
+    #[test]
+    fn the_best_run_keeps_its_replay() {
+        let store: Store = Arc::new(MemoryStorage::new());
+        let mut state = Game::with_seed(store.clone(), 1).get_state();
+        let profiles = ProfileManager::new(store.clone());
+        let replays = ReplayManager::new(store.clone());
+        let expiry = |id: &str| store.scan(&format!("replay:{}", id))[0].expires_at;
+
+        let first = replays.save(1, vec![], 10);
+        state.score = 10;
+        profiles.record_run("ann", &state, 10, Some(&first.id));
+        assert_eq!(expiry(&first.id), None);
+
+        let second = replays.save(2, vec![], 20);
+        state.score = 20;
+        profiles.record_run("ann", &state, 20, Some(&second.id));
+        assert_eq!(expiry(&second.id), None);
+        assert!(expiry(&first.id).is_some());
+
+        // Once the replay is gone the best run stops pointing at it
+        store.delete(&format!("replay:{}", second.id)).unwrap();
+        assert_eq!(profiles.get("ann").best_run.unwrap().replay_id, None);
+    }
+
+    #[test]
+    fn concurrent_runs_are_all_counted() {
+        let store: Store = Arc::new(MemoryStorage::new());
+        let state = Game::with_seed(store.clone(), 1).get_state();
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let (store, state) = (store.clone(), state.clone());
+                thread::spawn(move || {
+                    for _ in 0..25 {
+                        ProfileManager::new(store.clone()).record_run("ann", &state, 10, None);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(ProfileManager::new(store).get("ann").games_played, 200);
+    }
+}
@@ -0,0 +1,127 @@
+use std::cmp::Reverse;
+use rand::{thread_rng, Rng};
+use serde::{Serialize, Deserialize};
+use tracing::error;
+use super::game::PlayerMove;
+use crate::storage::{self, Store};
+
+const REPLAY_PREFIX: &str = "replay:";
+const REPLAY_TTL: u64 = 7 * 24 * 60 * 60; // one week in seconds
+
+// Everything needed to re-run a game: the course comes from the seed and the
+// player's inputs are replayed at the ticks they happened.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Replay {
+    pub id: String,
+    pub seed: u64,
+    pub inputs: Vec<(u32, PlayerMove)>,
+    pub score: u32,
+    pub recorded_at: u64,
+}
+
+pub struct ReplayManager {
+    store: Store,
+}
+
+impl ReplayManager {
+    pub fn new(store: Store) -> Self {
+        Self { store }
+    }
+
+    // Ids are the recording time plus a random suffix, so games that end in
+    // the same second get their own replays. A suffix that is already taken
+    // is drawn again.
+    pub fn save(&self, seed: u64, inputs: Vec<(u32, PlayerMove)>, score: u32) -> Replay {
+        let recorded_at = storage::now();
+        let mut replay = Replay {
+            id: String::new(),
+            seed,
+            inputs,
+            score,
+            recorded_at,
+        };
+
+        loop {
+            replay.id = format!("{:x}-{:04x}", recorded_at, thread_rng().gen::<u16>());
+            let key = format!("{}{}", REPLAY_PREFIX, replay.id);
+            let json = serde_json::to_string(&replay).unwrap();
+            match self.store.insert_new(&key, &json, Some(REPLAY_TTL)) {
+                Ok(true) => return replay,
+                Ok(false) => continue,
+                Err(e) => {
+                    error!(error = %e, "failed to save replay");
+                    return replay;
+                }
+            }
+        }
+    }
+
+    // All stored replays, newest first
+    pub fn list(&self) -> Vec<Replay> {
+        let mut replays: Vec<Replay> = self.store
+            .scan(REPLAY_PREFIX)
+            .into_iter()
+            .filter_map(|entry| serde_json::from_str(entry.value.trim_matches('"')).ok())
+            .collect();
+        replays.sort_by_key(|replay| Reverse(replay.recorded_at));
+        replays
+    }
+
+    pub fn get(&self, id: &str) -> Option<Replay> {
+        self.store
+            .get(&format!("{}{}", REPLAY_PREFIX, id))
+            .and_then(|data| serde_json::from_str(data.trim_matches('"')).ok())
+    }
+
+    // A player's best run keeps its replay until a better run replaces it,
+    // which hands the old one back its expiry. Returns whether the replay
+    // was still there.
+    pub fn set_kept(&self, id: &str, kept: bool) -> bool {
+        let Some(replay) = self.get(id) else {
+            return false;
+        };
+        let ttl = if kept { None } else { Some(REPLAY_TTL) };
+        let json = serde_json::to_string(&replay).unwrap();
+        match self.store.set(&format!("{}{}", REPLAY_PREFIX, id), &json, ttl) {
+            Ok(_) => true,
+            Err(e) => {
+                error!(replay_id = id, error = %e, "failed to update replay expiry");
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::storage::MemoryStorage;
+    use super::*;
+
+    #[test]
+    fn replays_saved_together_are_kept_apart() {
+        let replays = ReplayManager::new(Arc::new(MemoryStorage::new()));
+        let first = replays.save(1, vec![], 10);
+        let second = replays.save(2, vec![], 20);
+
+        assert_ne!(first.id, second.id);
+        assert_eq!(replays.get(&first.id).unwrap().seed, 1);
+        assert_eq!(replays.get(&second.id).unwrap().seed, 2);
+        assert_eq!(replays.list().len(), 2);
+    }
+
+    #[test]
+    fn kept_replays_lose_their_expiry_until_released() {
+        let store: Store = Arc::new(MemoryStorage::new());
+        let replays = ReplayManager::new(store.clone());
+        let replay = replays.save(1, vec![], 10);
+        let expiry = || store.scan(REPLAY_PREFIX)[0].expires_at;
+        assert!(expiry().is_some());
+
+        assert!(replays.set_kept(&replay.id, true));
+        assert_eq!(expiry(), None);
+        assert!(replays.set_kept(&replay.id, false));
+        assert!(expiry().is_some());
+        assert!(!replays.set_kept("missing", true));
+    }
+}
@@ -1,28 +1,60 @@
-use std::sync::{Arc, Mutex};
+use std::cmp::Reverse;
 use serde::{Serialize, Deserialize};
 use std::io::{self, Write};
-use std::time::SystemTime;
-use simple_kv_store::KvStore;
 use rand::{thread_rng, Rng};
+use tracing::{debug, error, info};
+use super::challenge::Challenge;
+use super::leaderboard::Board;
+use super::player::{Player, PlayerError, PlayerManager, MAX_NAME_LENGTH};
+use super::scoring::ScoreBreakdown;
+use crate::storage::{self, Entry, Store};
 
 const HISCORE_PREFIX: &str = "hiscore:";
 const HISCORE_TTL_KEY: &str = "hiscore_ttl";
 const DEFAULT_TTL: u64 = 300; // 5 minutes in seconds
+pub const BOARD_SIZE_KEY: &str = "leaderboard_size";
+const DEFAULT_BOARD_SIZE: usize = 3;
+pub const BOARDS_KEY: &str = "leaderboard_boards";
 const NANOID_LENGTH: usize = 8;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct HiScore {
     pub name: String,
     pub score: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player_id: Option<String>,
+    // Missing on scores set before near misses were scored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<ScoreBreakdown>,
+    // When the score was set, which breaks ties on the board. Scores saved
+    // before it was stored here only have it at the end of their key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+}
+
+// Where a newly recorded score landed on one board, 1 being the top
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Placement {
+    pub board: Board,
+    pub rank: usize,
+}
+
+pub struct ScoreOutcome {
+    pub placements: Vec<Placement>,
+    pub hiscores: Vec<(String, u32, Option<u64>)>,
 }
 
 pub struct ScoreManager {
-    store: Arc<Mutex<KvStore>>,
+    store: Store,
+    players: PlayerManager,
 }
 
 impl ScoreManager {
-    pub fn new(store: Arc<Mutex<KvStore>>) -> Self {
-        Self { store }
+    pub fn new(store: Store) -> Self {
+        Self {
+            players: PlayerManager::new(store.clone()),
+            store,
+        }
     }
 
     fn generate_nanoid() -> String {
@@ -41,6 +73,7 @@ impl ScoreManager {
         let sanitized: String = input
             .chars()
             .filter(|c| c.is_ascii_alphabetic())
+            .take(MAX_NAME_LENGTH)
             .collect();
 
         if sanitized.is_empty() {
@@ -73,76 +106,311 @@ impl ScoreManager {
     }
 
     fn get_ttl(&self) -> u64 {
-        let store = self.store.lock().unwrap();
-        store.get(HISCORE_TTL_KEY)
-            .and_then(|ttl_str| ttl_str.parse().ok())
+        self.store.get(HISCORE_TTL_KEY)
+            .and_then(|ttl_str| ttl_str.trim_matches('"').parse().ok())
             .unwrap_or(DEFAULT_TTL)
     }
 
-    fn get_hiscores(&self) -> Vec<(HiScore, Option<u64>)> {
-        let store = self.store.lock().unwrap();
-        let mut scores = Vec::new();
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-
-        for (key, value) in store.get_all() {
-            if key.starts_with(HISCORE_PREFIX) {
-                let data_str = value.data.to_string();
-                let data_str = data_str.trim_matches('"');
-                
-                if let Ok(score) = serde_json::from_str::<HiScore>(data_str) {
-                    let remaining_ttl = value.expires_at.map(|expires_at| {
-                        if expires_at > now {
-                            expires_at - now
-                        } else {
-                            0
-                        }
-                    });
-                    scores.push((score, remaining_ttl));
-                }
+    // How many entries each board keeps
+    pub fn board_size(&self) -> usize {
+        self.store.get(BOARD_SIZE_KEY)
+            .and_then(|size| size.trim_matches('"').parse().ok())
+            .filter(|&size| size > 0)
+            .unwrap_or(DEFAULT_BOARD_SIZE)
+    }
+
+    // Boards new scores are recorded on
+    pub fn boards(&self) -> Vec<Board> {
+        self.store.get(BOARDS_KEY)
+            .and_then(|boards| serde_json::from_str::<Vec<Board>>(&boards).ok())
+            .filter(|boards| !boards.is_empty())
+            .unwrap_or_else(|| Board::ALL.to_vec())
+    }
+
+    fn board_prefix(board: Board, now: u64) -> String {
+        format!("{}{}", HISCORE_PREFIX, board.key_segment(now))
+    }
+
+    fn challenge_prefix(challenge: &Challenge) -> String {
+        format!("{}challenge-{}:", HISCORE_PREFIX, challenge.date)
+    }
+
+    // The first `limit` entries under a board prefix in rank order: higher
+    // score first, and between equal scores the one set earlier. The store
+    // returns them in that order.
+    fn ranked(&self, prefix: &str, limit: usize) -> Vec<(HiScore, Entry)> {
+        self.store.top_n(prefix, "score", limit)
+            .into_iter()
+            .filter_map(|entry| {
+                let score = serde_json::from_str::<HiScore>(entry.value.trim_matches('"')).ok()?;
+                Some((score, entry))
+            })
+            .collect()
+    }
+
+    // The best `limit` high scores under a board prefix, highest first
+    fn get_hiscores(&self, prefix: &str, limit: usize) -> Vec<(HiScore, Option<u64>)> {
+        let now = storage::now();
+        self.ranked(prefix, limit)
+            .into_iter()
+            .map(|(score, entry)| {
+                let remaining_ttl = entry.expires_at.map(|expires_at| expires_at.saturating_sub(now));
+                (score, remaining_ttl)
+            })
+            .collect()
+    }
+
+    // Every high score a player still has on record, newest first, with the
+    // time it was set. Keys end in that time, and a score kept on several
+    // boards is listed once.
+    pub fn player_history(&self, player_id: &str) -> Vec<(u32, u64)> {
+        let mut history: Vec<(u32, u64)> = self.store.find_by(HISCORE_PREFIX, "player_id", player_id)
+            .into_iter()
+            .filter_map(|entry| {
+                let timestamp = key_timestamp(&entry.key)?;
+                let score = serde_json::from_str::<HiScore>(entry.value.trim_matches('"')).ok()?;
+                Some((score.score, timestamp))
+            })
+            .collect();
+        history.sort_by_key(|&(score, timestamp)| (Reverse(timestamp), Reverse(score)));
+        history.dedup();
+        history
+    }
+
+    fn save_hiscore(&self, board: Board, score: &HiScore) {
+        let timestamp = storage::now();
+        let ttl = board.ttl(timestamp, self.get_ttl());
+        self.save_under(&Self::board_prefix(board, timestamp), score, timestamp, ttl);
+    }
+
+    fn save_under(&self, prefix: &str, score: &HiScore, timestamp: u64, ttl: Option<u64>) {
+        let owner = score.player_id.as_deref().unwrap_or(&score.name);
+        let key = format!("{}{}-{}-{}", prefix, owner, score.score, timestamp);
+        let json = serde_json::to_string(&HiScore { timestamp: Some(timestamp), ..score.clone() }).unwrap();
+
+        match self.store.set(&key, &json, ttl) {
+            Ok(_) => info!(key = %key, score = score.score, ttl, "high score saved"),
+            Err(e) => error!(key = %key, error = %e, "failed to save high score"),
+        }
+    }
+
+    // Drops the entry that no longer makes the top of the board. Boards are
+    // pruned after every new score, so at most one is over the size.
+    fn prune(&self, board: Board) {
+        let prefix = Self::board_prefix(board, storage::now());
+        let size = self.board_size();
+        for (_, entry) in self.ranked(&prefix, size + 1).iter().skip(size) {
+            match self.store.delete(&entry.key) {
+                Ok(_) => debug!(key = %entry.key, board = %board, "pruned high score"),
+                Err(e) => error!(key = %entry.key, error = %e, "failed to prune high score"),
             }
         }
+    }
+
+    // The rank a new score would take on a board, or None if it doesn't beat
+    // the board's last place. A new score is always the latest, so it ranks
+    // below any score it ties with.
+    fn rank_of(&self, board: Board, score: u32) -> Option<usize> {
+        let size = self.board_size();
+        let ahead = self.ranked(&Self::board_prefix(board, storage::now()), size)
+            .iter()
+            .filter(|(hiscore, _)| hiscore.score >= score)
+            .count();
+        Some(ahead + 1).filter(|&rank| rank <= size)
+    }
+
+    // Enabled boards the score would make it onto, with its rank on each
+    fn placements(&self, score: u32) -> Vec<Placement> {
+        self.boards()
+            .into_iter()
+            .filter_map(|board| self.rank_of(board, score).map(|rank| Placement { board, rank }))
+            .collect()
+    }
+
+    fn hiscore(player: &Player, breakdown: &ScoreBreakdown) -> HiScore {
+        HiScore {
+            name: player.name.clone(),
+            score: breakdown.total(),
+            player_id: Some(player.id.clone()),
+            breakdown: Some(breakdown.clone()),
+            timestamp: None,
+        }
+    }
+
+    fn record(&self, placements: &[Placement], score: HiScore) {
+        for placement in placements {
+            self.save_hiscore(placement.board, &score);
+            self.prune(placement.board);
+        }
+    }
 
-        scores.sort_by(|a, b| b.0.score.cmp(&a.0.score));
-        scores
-    }
-
-    fn save_hiscore(&self, score: HiScore) {
-        let ttl = self.get_ttl();
-        let mut store = self.store.lock().unwrap();
-        
-        let timestamp = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        
-        let key = format!("{}{}-{}-{}", HISCORE_PREFIX, score.name, score.score, timestamp);
-        let json = serde_json::to_string(&score).unwrap();
-        
-        store.set_with_ttl(key, json, Some(ttl))
-            .expect("Failed to save high score");
-    }
-
-    pub fn handle_new_score(&self, score: u32) -> Vec<(String, u32, Option<u64>)> {
-        let hiscores = self.get_hiscores();
-        let is_high_score = hiscores.len() < 3 || 
-                           hiscores.is_empty() || 
-                           score > hiscores.last().map_or(0, |last| last.0.score);
-
-        if is_high_score {
-            println!("\nCongratulations! You made the top 3!");
-            println!(); // Add a blank line before name prompt
+    // Terminal play has no login: a new name is registered on the spot, and
+    // an existing one needs that player's token
+    pub fn prompt_player(&self) -> Player {
+        loop {
             let name = Self::get_valid_name();
-            self.save_hiscore(HiScore { name, score });
+            match self.players.register(&name) {
+                Ok((player, token)) => {
+                    println!("Registered {}. Your token is {}", player.name, token);
+                    println!("Keep it to play as {} again, here or against a server.", player.name);
+                    return player;
+                }
+                Err(PlayerError::NameTaken) => {
+                    if let Some(player) = self.sign_in(&name) {
+                        return player;
+                    }
+                }
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+
+    fn sign_in(&self, name: &str) -> Option<Player> {
+        print!("{} is already registered. Enter its token, or press Enter to choose another name: ", name);
+        io::stdout().flush().unwrap();
+
+        let mut token = String::new();
+        io::stdin().read_line(&mut token).unwrap();
+        let token = token.trim();
+        if token.is_empty() {
+            return None;
+        }
+
+        match self.players.authenticate(token) {
+            Some(player) if player.name.eq_ignore_ascii_case(name) => Some(player),
+            _ => {
+                println!("That token doesn't belong to {}", name);
+                None
+            }
+        }
+    }
+
+    pub fn handle_new_score(&self, player: &Player, breakdown: &ScoreBreakdown) -> ScoreOutcome {
+        let placements = self.placements(breakdown.total());
+        if !placements.is_empty() {
+            println!("\nCongratulations! You made the top {}!", self.board_size());
+            self.record(&placements, Self::hiscore(player, breakdown));
         }
 
         // Return the high scores for the UI to display
-        self.get_hiscores()
-            .iter()
-            .take(3)
-            .map(|(score, ttl)| (score.name.clone(), score.score, *ttl))
+        ScoreOutcome { placements, hiscores: self.top_scores(self.default_board()) }
+    }
+
+    // Records a score for an authenticated player without prompting.
+    pub fn submit_score(&self, player: &Player, breakdown: &ScoreBreakdown) -> ScoreOutcome {
+        let placements = self.placements(breakdown.total());
+        self.record(&placements, Self::hiscore(player, breakdown));
+
+        ScoreOutcome { placements, hiscores: self.top_scores(self.default_board()) }
+    }
+
+    // The board shown when none is asked for: the default if it is enabled,
+    // otherwise the first enabled one
+    pub fn default_board(&self) -> Board {
+        let boards = self.boards();
+        if boards.contains(&Board::DEFAULT) {
+            Board::DEFAULT
+        } else {
+            boards[0]
+        }
+    }
+
+    pub fn top_scores(&self, board: Board) -> Vec<(String, u32, Option<u64>)> {
+        self.top_under(&Self::board_prefix(board, storage::now()))
+    }
+
+    fn top_under(&self, prefix: &str) -> Vec<(String, u32, Option<u64>)> {
+        self.get_hiscores(prefix, self.board_size())
+            .into_iter()
+            .map(|(score, ttl)| (score.name, score.score, ttl))
             .collect()
     }
-} 
\ No newline at end of file
+
+    // Every attempt at a challenge stays on its board until the challenge
+    // closes, so the rank is among everyone who played it
+    pub fn submit_challenge(&self, player: &Player, challenge: &Challenge, breakdown: &ScoreBreakdown) -> usize {
+        let prefix = Self::challenge_prefix(challenge);
+        let ahead = self.ranked(&prefix, usize::MAX)
+            .iter()
+            .filter(|(hiscore, _)| hiscore.score >= breakdown.total())
+            .count();
+
+        let hiscore = Self::hiscore(player, breakdown);
+        self.save_under(&prefix, &hiscore, storage::now(), Some(challenge.ttl()));
+        ahead + 1
+    }
+
+    pub fn challenge_scores(&self, challenge: &Challenge) -> Vec<(String, u32, Option<u64>)> {
+        self.top_under(&Self::challenge_prefix(challenge))
+    }
+}
+
+// Keys end in the time the score was set
+fn key_timestamp(key: &str) -> Option<u64> {
+    key.rsplit('-').next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::storage::MemoryStorage;
+    use super::*;
+
+    // A manager keeping only the all-time board, at the default size of 3
+    fn scores() -> ScoreManager {
+        let store: Store = Arc::new(MemoryStorage::new());
+        store.set(BOARDS_KEY, r#"["all-time"]"#, None).unwrap();
+        ScoreManager::new(store)
+    }
+
+    fn seed(scores: &ScoreManager, name: &str, score: u32, timestamp: u64) {
+        let hiscore = HiScore { name: name.to_string(), score, player_id: None, breakdown: None, timestamp: None };
+        let prefix = ScoreManager::board_prefix(Board::AllTime, storage::now());
+        scores.save_under(&prefix, &hiscore, timestamp, None);
+    }
+
+    fn names(scores: &ScoreManager) -> Vec<String> {
+        scores.top_scores(Board::AllTime).into_iter().map(|(name, _, _)| name).collect()
+    }
+
+    #[test]
+    fn last_place_goes_to_a_score_that_beats_it() {
+        let scores = scores();
+        seed(&scores, "Ann", 30, 100);
+        seed(&scores, "Bob", 20, 200);
+        seed(&scores, "Cat", 10, 300);
+
+        let placements = scores.placements(11);
+        assert_eq!(placements.len(), 1);
+        assert_eq!(placements[0].rank, 3);
+        // Tying last place isn't enough, since the new score was set later
+        assert!(scores.placements(10).is_empty());
+    }
+
+    #[test]
+    fn equal_scores_rank_by_time_then_key() {
+        let scores = scores();
+        seed(&scores, "Ann", 20, 200);
+        seed(&scores, "Cat", 20, 100);
+        seed(&scores, "Bob", 20, 100);
+
+        assert_eq!(names(&scores), ["Bob", "Cat", "Ann"]);
+    }
+
+    #[test]
+    fn recorded_rank_matches_the_pruned_board() {
+        let scores = scores();
+        seed(&scores, "Ann", 30, 100);
+        seed(&scores, "Bob", 20, 200);
+        seed(&scores, "Cat", 10, 300);
+
+        let player = Player { id: "dee".to_string(), name: "Dee".to_string() };
+        let breakdown = ScoreBreakdown { distance: 25, ..Default::default() };
+        let outcome = scores.submit_score(&player, &breakdown);
+
+        assert_eq!(outcome.placements.len(), 1);
+        assert_eq!(outcome.placements[0].rank, 2);
+        assert_eq!(names(&scores), ["Ann", "Dee", "Bob"]);
+        assert_eq!(scores.store.scan(HISCORE_PREFIX).len(), 3);
+    }
+}
@@ -0,0 +1,96 @@
+use serde::{Serialize, Deserialize};
+
+// Points for a near miss before the combo multiplier
+pub const NEAR_MISS_BONUS: u32 = 10;
+const MAX_MULTIPLIER: u32 = 5;
+// Ticks without a near miss before the combo drops, 3 seconds of play
+const COMBO_TIMEOUT: u32 = 15;
+
+// How a run's score was made up. The score is always `total()`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ScoreBreakdown {
+    // One point per tick survived
+    pub distance: u32,
+    pub near_misses: u32,
+    // Points from near misses, multipliers included
+    pub bonus: u32,
+    // Longest run of near misses in one combo
+    pub combo_peak: u32,
+}
+
+impl ScoreBreakdown {
+    pub fn total(&self) -> u32 {
+        self.distance + self.bonus
+    }
+}
+
+// Near misses in a row, each within COMBO_TIMEOUT ticks of the last
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Combo {
+    pub count: u32,
+    // Ticks left before the combo drops
+    pub ticks_left: u32,
+}
+
+impl Combo {
+    pub fn multiplier(&self) -> u32 {
+        self.count.clamp(1, MAX_MULTIPLIER)
+    }
+
+    // Counts down one tick of play and drops the combo once it runs out
+    pub fn tick(&mut self) {
+        self.ticks_left = self.ticks_left.saturating_sub(1);
+        if self.ticks_left == 0 {
+            self.count = 0;
+        }
+    }
+
+    // Extends the combo with a near miss and scores it. Returns the points
+    // awarded.
+    pub fn near_miss(&mut self, breakdown: &mut ScoreBreakdown) -> u32 {
+        self.count += 1;
+        self.ticks_left = COMBO_TIMEOUT;
+
+        let points = NEAR_MISS_BONUS * self.multiplier();
+        breakdown.near_misses += 1;
+        breakdown.bonus += points;
+        breakdown.combo_peak = breakdown.combo_peak.max(self.count);
+        points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_near_miss_in_a_combo_is_worth_more_up_to_the_cap() {
+        let mut combo = Combo::default();
+        let mut breakdown = ScoreBreakdown::default();
+
+        let points: Vec<u32> = (0..7).map(|_| combo.near_miss(&mut breakdown)).collect();
+
+        assert_eq!(points, [10, 20, 30, 40, 50, 50, 50]);
+        assert_eq!(breakdown.near_misses, 7);
+        assert_eq!(breakdown.bonus, 250);
+        assert_eq!(breakdown.combo_peak, 7);
+    }
+
+    #[test]
+    fn combo_drops_after_the_timeout() {
+        let mut combo = Combo::default();
+        let mut breakdown = ScoreBreakdown::default();
+        combo.near_miss(&mut breakdown);
+        combo.near_miss(&mut breakdown);
+
+        for _ in 1..COMBO_TIMEOUT {
+            combo.tick();
+        }
+        assert_eq!(combo.multiplier(), 2);
+
+        combo.tick();
+        assert_eq!(combo.count, 0);
+        assert_eq!(combo.near_miss(&mut breakdown), NEAR_MISS_BONUS);
+        assert_eq!(breakdown.combo_peak, 2);
+    }
+}
@@ -0,0 +1,72 @@
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+const LOG_ENV_VAR: &str = "SIDE_SCROLLER_LOG";
+const DEFAULT_LOG_FILE: &str = "side_scroller.log";
+
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Unknown log format '{}', expected text or json", s)),
+        }
+    }
+}
+
+pub struct LogOptions {
+    // Filter directive such as "debug" or "side_scroller=debug,warp=info".
+    // Falls back to $SIDE_SCROLLER_LOG, then $RUST_LOG, then "info".
+    pub level: Option<String>,
+    pub format: LogFormat,
+    // Where to write logs. Interactive modes default to a file so log lines
+    // never land on the raw-mode game screen; the server logs to stderr.
+    pub file: Option<PathBuf>,
+    pub interactive: bool,
+}
+
+pub fn init(options: LogOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let directive = options.level
+        .or_else(|| std::env::var(LOG_ENV_VAR).ok())
+        .or_else(|| std::env::var("RUST_LOG").ok())
+        .unwrap_or_else(|| "info".to_string());
+    let filter = EnvFilter::try_new(&directive)?;
+
+    let file = match options.file {
+        Some(path) => Some(path),
+        None if options.interactive => Some(PathBuf::from(DEFAULT_LOG_FILE)),
+        None => None,
+    };
+
+    let (writer, ansi) = match file {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            (BoxMakeWriter::new(Mutex::new(file)), false)
+        }
+        None => (BoxMakeWriter::new(std::io::stderr), true),
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(ansi);
+
+    match options.format {
+        LogFormat::Json => builder.json().try_init(),
+        LogFormat::Text => builder.try_init(),
+    }
+    .map_err(|e| e.to_string().into())
+}
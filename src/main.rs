@@ -1,66 +1,162 @@
 mod core;
+mod config;
+mod logging;
+mod persistence;
 mod ui;
 mod cli;
+mod client;
 mod server;
+mod storage;
 
-use std::{thread, time::Duration};
-use crossterm::{
-    execute,
-    terminal::{Clear, ClearType, enable_raw_mode, disable_raw_mode},
-    cursor::Hide,
-};
-use std::sync::{Arc, Mutex};
-use simple_kv_store::KvStore;
-use std::env;
+use std::time::Duration;
+use std::sync::Arc;
+use std::net::{IpAddr, SocketAddr};
+use clap::Parser;
 
-use crate::cli::{CLI, GameRunner};
-use crate::core::Game;
-use crate::ui::{render_game, handle_input, ask_play_again};
-use crate::server::GameServer;
+use crate::cli::{Args, Command, CLI, DbArgs, DbCommand, GameRunner, PlayArgs, ServeArgs};
+use crate::config::{Config, ServerConfig};
+use crate::logging::{LogFormat, LogOptions};
+use crate::persistence::file::FileStorage;
+use crate::server::{GameServer, RateLimitConfig};
+use crate::storage::{KvStorage, MemoryStorage, Store};
 
 pub const GAME_WIDTH: usize = 40;
 pub const FRAME_DURATION: Duration = Duration::from_millis(200);
 pub const OBSTACLE_CHANCE: f64 = 0.3;
 pub const INITIAL_OBSTACLE_DENSITY: f64 = 0.2;
+pub const PICKUP_CHANCE: f64 = 0.05;
+
+const DEFAULT_PORT: u16 = 3000;
+const DEFAULT_SNAPSHOT_INTERVAL_SECS: u64 = 5;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let store = Arc::new(Mutex::new(KvStore::new()?));
-    
-    let args: Vec<String> = env::args().collect();
-    match args.get(1).map(String::as_str) {
-        Some("--cli") => run_cli_mode(store),
-        Some("--server") => {
-            let port = args.get(2)
-                .and_then(|p| p.parse().ok())
-                .unwrap_or(3000);
-            run_server_mode(store, port).await
+    let args = Args::parse();
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    logging::init(LogOptions {
+        level: args.log_level.clone().or(config.log.level),
+        format: args.log_format.or(config.log.format).unwrap_or(LogFormat::Text),
+        file: args.log_file.clone().or(config.log.file),
+        interactive: !matches!(args.command, Some(Command::Serve(_))),
+    })?;
+
+    let store = open_store(&args)?;
+
+    match args.command.unwrap_or(Command::Play(PlayArgs::default())) {
+        Command::Play(play) => run_terminal_mode(store, play),
+        Command::Serve(serve) => run_server_mode(store, serve, config.server).await,
+        Command::Db(db) => run_db_mode(store, db),
+        Command::Connect { url, token, race, room } => {
+            if race || room.is_some() {
+                client::run_race(&url, token, room).await
+            } else {
+                client::run_remote(&url, token).await
+            }
+        }
+        Command::Watch { url, game_id } => client::run_watch(&url, &game_id).await,
+        Command::Replay { id, list } => run_replay_mode(store, id, list),
+        Command::Stats { name } => {
+            GameRunner::new(store).show_stats(&name);
+            Ok(())
+        }
+        Command::Scores { player, board, challenge } => {
+            let runner = GameRunner::new(store);
+            match player {
+                Some(name) => runner.show_player_scores(&name),
+                None if challenge => runner.show_challenge(),
+                None => runner.show_scores(board),
+            }
+            Ok(())
         }
-        Some("--db") => run_db_mode(store),
-        _ => run_terminal_mode(store),
     }
 }
 
-fn run_cli_mode(store: Arc<Mutex<KvStore>>) -> Result<(), Box<dyn std::error::Error>> {
-    let runner = GameRunner::new(store);
-    runner.run();
-    Ok(())
+fn open_store(args: &Args) -> Result<Store, Box<dyn std::error::Error>> {
+    if args.in_memory {
+        return Ok(Arc::new(MemoryStorage::new()));
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.sqlite {
+        return Ok(Arc::new(persistence::sqlite::SqliteStorage::open(path)?));
+    }
+    match &args.store_path {
+        Some(path) => Ok(Arc::new(FileStorage::open(path)?)),
+        None => Ok(Arc::new(KvStorage::new()?)),
+    }
 }
 
-fn run_db_mode(store: Arc<Mutex<KvStore>>) -> Result<(), Box<dyn std::error::Error>> {
+fn run_db_mode(store: Store, db: DbArgs) -> Result<(), Box<dyn std::error::Error>> {
     let cli = CLI::new(store);
-    cli.run();
+    let code = match db.command {
+        None => {
+            cli.run();
+            return Ok(());
+        }
+        Some(DbCommand::Exec { command }) => cli.exec(&command, db.json),
+        Some(DbCommand::Run { script }) => cli.run_script(&script, db.json),
+    };
+    if code != 0 {
+        std::process::exit(code);
+    }
     Ok(())
 }
 
-async fn run_server_mode(store: Arc<Mutex<KvStore>>, port: u16) -> Result<(), Box<dyn std::error::Error>> {
-    let server = GameServer::new(store);
-    server.run(port).await;
+async fn run_server_mode(
+    store: Store,
+    serve: ServeArgs,
+    config: ServerConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let defaults = RateLimitConfig::default();
+    let rate_limits = RateLimitConfig {
+        new_games_per_minute: serve.new_games_per_minute
+            .or(config.new_games_per_minute)
+            .unwrap_or(defaults.new_games_per_minute),
+        moves_per_second: serve.moves_per_second
+            .or(config.moves_per_second)
+            .unwrap_or(defaults.moves_per_second),
+        max_body_bytes: serve.max_body_bytes
+            .or(config.max_body_bytes)
+            .unwrap_or(defaults.max_body_bytes),
+    };
+    let port = serve.port.or(config.port).unwrap_or(DEFAULT_PORT);
+    let bind = serve.bind.or(config.bind).unwrap_or(IpAddr::from([127, 0, 0, 1]));
+    let snapshot_interval = Duration::from_secs(
+        serve.snapshot_interval
+            .or(config.snapshot_interval_secs)
+            .unwrap_or(DEFAULT_SNAPSHOT_INTERVAL_SECS)
+    );
+
+    let server = GameServer::new(store)
+        .with_rate_limits(rate_limits)
+        .with_snapshot_interval(snapshot_interval);
+    server.run(SocketAddr::new(bind, port)).await;
     Ok(())
 }
 
-fn run_terminal_mode(store: Arc<Mutex<KvStore>>) -> Result<(), Box<dyn std::error::Error>> {
+fn run_replay_mode(
+    store: Store,
+    id: Option<String>,
+    list: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     let runner = GameRunner::new(store);
-    runner.run();
+    if list {
+        runner.list_replays();
+        return Ok(());
+    }
+    runner.replay(id.as_deref())?;
     Ok(())
-} 
\ No newline at end of file
+}
+
+fn run_terminal_mode(store: Store, play: PlayArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let runner = GameRunner::new(store).with_seed(play.seed);
+    if play.daily {
+        runner.run_daily();
+    } else {
+        runner.run();
+    }
+    Ok(())
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use serde::{Serialize, Deserialize};
+use tracing::{debug, info, warn};
+use crate::storage::{now, validate, Entry, Storage, StorageError};
+
+// The log is rewritten once it holds at least this many records that no
+// longer describe a live key, and those outnumber the live keys
+const COMPACT_MIN_STALE: usize = 1000;
+
+// One line of the log. Replaying every record in order rebuilds the store.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    Set { key: String, value: String, expires_at: Option<u64> },
+    Delete { key: String },
+}
+
+struct Slot {
+    value: String,
+    expires_at: Option<u64>,
+}
+
+impl Slot {
+    fn is_live(&self, now: u64) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+struct Log {
+    path: PathBuf,
+    file: File,
+    entries: HashMap<String, Slot>,
+    // Records currently in the file, including superseded ones
+    records: usize,
+}
+
+// Append-only log of JSON records, kept in memory as a map and fsynced on
+// every write. A crash can only ever tear the final line, which is dropped
+// the next time the log is opened. Any other line that doesn't parse means
+// the file is damaged or isn't a store log, and opening it fails.
+pub struct FileStorage {
+    log: Mutex<Log>,
+}
+
+impl FileStorage {
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        let mut entries = HashMap::new();
+        let mut records = 0;
+        let mut valid_len = 0;
+
+        if path.exists() {
+            let mut reader = BufReader::new(File::open(path)?);
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                let read = reader.read_until(b'\n', &mut line)?;
+                if read == 0 {
+                    break;
+                }
+                // Without its newline the record was cut off mid-write
+                if line.last() != Some(&b'\n') {
+                    if !is_torn_record(&line) {
+                        return Err(StorageError(format!(
+                            "{}: line {} is not a store record", path.display(), records + 1)));
+                    }
+                    break;
+                }
+                let record = serde_json::from_slice::<Record>(&line).map_err(|e| StorageError(format!(
+                    "{}: corrupt record on line {}: {}", path.display(), records + 1, e)))?;
+                apply(&mut entries, record);
+                records += 1;
+                valid_len += read as u64;
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let file_len = file.metadata()?.len();
+        if file_len > valid_len {
+            warn!(path = %path.display(), discarded_bytes = file_len - valid_len, "dropping torn record at end of store log");
+            file.set_len(valid_len)?;
+            file.sync_all()?;
+        }
+        info!(path = %path.display(), keys = entries.len(), records, "store log opened");
+
+        let mut log = Log { path: path.to_path_buf(), file, entries, records };
+        log.compact_if_needed()?;
+        Ok(Self { log: Mutex::new(log) })
+    }
+}
+
+// Whether a line without its newline could be the start of a record, as
+// opposed to the contents of some other file
+fn is_torn_record(line: &[u8]) -> bool {
+    const START: &[u8] = br#"{"op":""#;
+    line.starts_with(START) || START.starts_with(line)
+}
+
+fn apply(entries: &mut HashMap<String, Slot>, record: Record) {
+    match record {
+        Record::Set { key, value, expires_at } => {
+            entries.insert(key, Slot { value, expires_at });
+        }
+        Record::Delete { key } => {
+            entries.remove(&key);
+        }
+    }
+}
+
+impl Log {
+    fn append(&mut self, record: &Record) -> Result<(), StorageError> {
+        let mut line = serde_json::to_vec(record).map_err(|e| StorageError(e.to_string()))?;
+        line.push(b'\n');
+        // One write per record keeps a torn write confined to the last line
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        self.records += 1;
+        Ok(())
+    }
+
+    fn set(&mut self, key: &str, value: &str, ttl: Option<u64>) -> Result<(), StorageError> {
+        let expires_at = ttl.map(|ttl| now() + ttl);
+        self.append(&Record::Set { key: key.to_string(), value: value.to_string(), expires_at })?;
+        self.entries.insert(key.to_string(), Slot { value: value.to_string(), expires_at });
+        self.compact_if_needed()
+    }
+
+    fn compact_if_needed(&mut self) -> Result<(), StorageError> {
+        let stale = self.records.saturating_sub(self.entries.len());
+        if stale >= COMPACT_MIN_STALE && stale > self.entries.len() {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    // Writes the live keys to a fresh file and renames it over the log, so a
+    // crash during compaction leaves the old log in place.
+    fn compact(&mut self) -> Result<(), StorageError> {
+        let now = now();
+        self.entries.retain(|_, slot| slot.is_live(now));
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".compact");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let tmp = File::create(&tmp_path)?;
+        let mut writer = BufWriter::new(&tmp);
+        for (key, slot) in &self.entries {
+            let record = Record::Set {
+                key: key.clone(),
+                value: slot.value.clone(),
+                expires_at: slot.expires_at,
+            };
+            serde_json::to_writer(&mut writer, &record).map_err(|e| StorageError(e.to_string()))?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        drop(writer);
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        // Persist the rename itself
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+
+        debug!(path = %self.path.display(), before = self.records, after = self.entries.len(), "store log compacted");
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.records = self.entries.len();
+        Ok(())
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        let log = self.log.lock().unwrap();
+        log.entries.get(key)
+            .filter(|slot| slot.is_live(now()))
+            .map(|slot| slot.value.clone())
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<u64>) -> Result<(), StorageError> {
+        validate(value)?;
+        self.log.lock().unwrap().set(key, value, ttl)
+    }
+
+    fn insert_new(&self, key: &str, value: &str, ttl: Option<u64>) -> Result<bool, StorageError> {
+        validate(value)?;
+        let mut log = self.log.lock().unwrap();
+        if log.entries.get(key).is_some_and(|slot| slot.is_live(now())) {
+            return Ok(false);
+        }
+        log.set(key, value, ttl).map(|_| true)
+    }
+
+    fn delete(&self, key: &str) -> Result<Option<String>, StorageError> {
+        let mut log = self.log.lock().unwrap();
+        if !log.entries.contains_key(key) {
+            return Ok(None);
+        }
+        log.append(&Record::Delete { key: key.to_string() })?;
+        let slot = log.entries.remove(key);
+        log.compact_if_needed()?;
+        Ok(slot.filter(|slot| slot.is_live(now())).map(|slot| slot.value))
+    }
+
+    fn scan(&self, prefix: &str) -> Vec<Entry> {
+        let now = now();
+        let log = self.log.lock().unwrap();
+        log.entries
+            .iter()
+            .filter(|(key, slot)| key.starts_with(prefix) && slot.is_live(now))
+            .map(|(key, slot)| Entry {
+                key: key.clone(),
+                value: slot.value.clone(),
+                expires_at: slot.expires_at,
+            })
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        let now = now();
+        self.log.lock().unwrap().entries.values().filter(|slot| slot.is_live(now)).count()
+    }
+
+    fn is_healthy(&self) -> bool {
+        !self.log.is_poisoned()
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        let log = self.log.lock().map_err(|_| StorageError("store lock poisoned".to_string()))?;
+        log.file.sync_all()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use uuid::Uuid;
+    use super::*;
+
+    const RECORDS: &str = concat!(
+        r#"{"op":"set","key":"a","value":"1","expires_at":null}"#, "\n",
+        r#"{"op":"set","key":"b","value":"2","expires_at":null}"#, "\n",
+    );
+
+    fn temp_log(contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("side_scroller-{}.log", Uuid::new_v4().simple()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn torn_final_record_is_dropped() {
+        let path = temp_log(&format!("{}{}", RECORDS, r#"{"op":"set","key":"c","va"#));
+
+        let store = FileStorage::open(&path).unwrap();
+        assert_eq!(store.get("a").as_deref(), Some("1"));
+        assert_eq!(store.get("b").as_deref(), Some("2"));
+        assert_eq!(store.get("c"), None);
+        assert_eq!(fs::read_to_string(&path).unwrap(), RECORDS);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_record_fails_to_open_and_keeps_the_log() {
+        let contents = format!("{}not a record\n{}", RECORDS, RECORDS);
+        let path = temp_log(&contents);
+
+        assert!(FileStorage::open(&path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_that_is_not_a_log_is_left_alone() {
+        let path = temp_log("just some notes");
+
+        assert!(FileStorage::open(&path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "just some notes");
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1 +1,3 @@
-pub mod file;
\ No newline at end of file
+pub mod file;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
@@ -0,0 +1,304 @@
+use std::cell::Cell;
+use std::path::Path;
+use std::sync::Mutex;
+use rusqlite::{named_params, Connection, OptionalExtension};
+use tracing::{debug, error, info};
+use crate::storage::{self, now, validate, Entry, Storage, StorageError};
+
+// Keys with these prefixes get their own table with columns pulled out of the
+// JSON value, so leaderboards and history are answered from an index. All
+// other keys (settings, tokens, sessions) share the generic `kv` table.
+const SCORES_PREFIX: &str = "hiscore:";
+const PLAYERS_PREFIX: &str = "player:";
+const RUNS_PREFIX: &str = "replay:";
+
+const TABLES: [(&str, &str); 4] = [
+    (SCORES_PREFIX, "scores"),
+    (PLAYERS_PREFIX, "players"),
+    (RUNS_PREFIX, "runs"),
+    ("", "kv"),
+];
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS kv (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL,
+        expires_at INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS scores (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL,
+        expires_at INTEGER,
+        player_id TEXT GENERATED ALWAYS AS (json_extract(value, '$.player_id')) STORED,
+        name TEXT GENERATED ALWAYS AS (json_extract(value, '$.name')) STORED,
+        score INTEGER GENERATED ALWAYS AS (json_extract(value, '$.score')) STORED
+    );
+    CREATE INDEX IF NOT EXISTS scores_by_score ON scores (score DESC);
+    CREATE INDEX IF NOT EXISTS scores_by_player ON scores (player_id);
+    CREATE TABLE IF NOT EXISTS players (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL,
+        expires_at INTEGER,
+        name TEXT GENERATED ALWAYS AS (json_extract(value, '$.name')) STORED
+    );
+    CREATE INDEX IF NOT EXISTS players_by_name ON players (name);
+    CREATE TABLE IF NOT EXISTS runs (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL,
+        expires_at INTEGER,
+        score INTEGER GENERATED ALWAYS AS (json_extract(value, '$.score')) STORED,
+        recorded_at INTEGER GENERATED ALWAYS AS (json_extract(value, '$.recorded_at')) STORED
+    );
+    CREATE INDEX IF NOT EXISTS runs_by_recorded_at ON runs (recorded_at DESC);
+";
+
+// Rows are purged once this many writes have happened since the last purge
+const PURGE_EVERY: u32 = 100;
+
+// A row is visible until its expiry time passes
+const LIVE: &str = "(expires_at IS NULL OR expires_at > :now)";
+
+fn table_for(key: &str) -> &'static str {
+    TABLES
+        .iter()
+        .find(|(prefix, _)| key.starts_with(prefix))
+        .map(|(_, table)| *table)
+        .unwrap()
+}
+
+// The dedicated table holding every key under `prefix`, if there is one.
+// Leaderboards live under sub-prefixes such as "hiscore:rolling:".
+fn indexed_table(prefix: &str) -> Option<&'static str> {
+    Some(table_for(prefix)).filter(|&table| table != "kv")
+}
+
+fn sql_error(e: rusqlite::Error) -> StorageError {
+    StorageError(e.to_string())
+}
+
+struct Db {
+    conn: Connection,
+    writes_since_purge: u32,
+    // Set once a read fails. Reads have no error to return, so this is how
+    // a broken database shows up in health checks instead of as empty results.
+    read_failed: Cell<bool>,
+}
+
+impl Db {
+    fn write(&mut self, key: &str, value: &str, ttl: Option<u64>) -> Result<(), StorageError> {
+        let expires_at = ttl.map(|ttl| now() + ttl);
+        let sql = format!(
+            "INSERT OR REPLACE INTO {} (key, value, expires_at) VALUES (:key, :value, :expires_at)",
+            table_for(key),
+        );
+        self.conn
+            .execute(&sql, named_params! { ":key": key, ":value": value, ":expires_at": expires_at })
+            .map_err(sql_error)?;
+        self.wrote()
+    }
+
+    fn wrote(&mut self) -> Result<(), StorageError> {
+        self.writes_since_purge += 1;
+        if self.writes_since_purge >= PURGE_EVERY {
+            self.purge_expired()?;
+        }
+        Ok(())
+    }
+
+    fn purge_expired(&mut self) -> Result<(), StorageError> {
+        let now = now();
+        let mut purged = 0;
+        for (_, table) in TABLES {
+            let sql = format!("DELETE FROM {} WHERE expires_at <= :now", table);
+            purged += self.conn.execute(&sql, named_params! { ":now": now }).map_err(sql_error)?;
+        }
+        self.writes_since_purge = 0;
+        if purged > 0 {
+            debug!(rows = purged, "purged expired rows");
+        }
+        Ok(())
+    }
+
+    fn live_value(&self, key: &str) -> Result<Option<String>, StorageError> {
+        let sql = format!("SELECT value FROM {} WHERE key = :key AND {}", table_for(key), LIVE);
+        self.conn
+            .query_row(&sql, named_params! { ":key": key, ":now": now() }, |row| row.get(0))
+            .optional()
+            .map_err(sql_error)
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Vec<Entry> {
+        let run = || -> rusqlite::Result<Vec<Entry>> {
+            let mut statement = self.conn.prepare_cached(sql)?;
+            let rows = statement.query_map(params, |row| {
+                Ok(Entry { key: row.get(0)?, value: row.get(1)?, expires_at: row.get(2)? })
+            })?;
+            rows.collect()
+        };
+        run().unwrap_or_else(|e| {
+            self.read_failed(&e);
+            Vec::new()
+        })
+    }
+
+    fn read_failed(&self, e: &dyn std::fmt::Display) {
+        error!(error = %e, "sqlite query failed");
+        self.read_failed.set(true);
+    }
+}
+
+pub struct SqliteStorage {
+    db: Mutex<Db>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        let conn = Connection::open(path).map_err(sql_error)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(())).map_err(sql_error)?;
+        conn.execute_batch(SCHEMA).map_err(sql_error)?;
+
+        let mut db = Db { conn, writes_since_purge: 0, read_failed: Cell::new(false) };
+        db.purge_expired()?;
+        info!(path = %path.display(), "sqlite store opened");
+        Ok(Self { db: Mutex::new(db) })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        let db = self.db.lock().unwrap();
+        db.live_value(key).unwrap_or_else(|e| {
+            db.read_failed(&e);
+            None
+        })
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<u64>) -> Result<(), StorageError> {
+        validate(value)?;
+        self.db.lock().unwrap().write(key, value, ttl)
+    }
+
+    fn insert_new(&self, key: &str, value: &str, ttl: Option<u64>) -> Result<bool, StorageError> {
+        validate(value)?;
+        let mut db = self.db.lock().unwrap();
+        if db.live_value(key)?.is_some() {
+            return Ok(false);
+        }
+        db.write(key, value, ttl).map(|_| true)
+    }
+
+    fn delete(&self, key: &str) -> Result<Option<String>, StorageError> {
+        let mut db = self.db.lock().unwrap();
+        let value = db.live_value(key)?;
+        let sql = format!("DELETE FROM {} WHERE key = :key", table_for(key));
+        db.conn.execute(&sql, named_params! { ":key": key }).map_err(sql_error)?;
+        db.wrote()?;
+        Ok(value)
+    }
+
+    fn scan(&self, prefix: &str) -> Vec<Entry> {
+        let db = self.db.lock().unwrap();
+        let now = now();
+        // A prefix can reach into several tables, e.g. "" or "p"
+        TABLES
+            .iter()
+            .filter(|(table_prefix, _)| table_prefix.starts_with(prefix) || prefix.starts_with(table_prefix))
+            .flat_map(|(_, table)| {
+                let sql = format!(
+                    "SELECT key, value, expires_at FROM {} WHERE substr(key, 1, length(:prefix)) = :prefix AND {}",
+                    table, LIVE,
+                );
+                db.query(&sql, named_params! { ":prefix": prefix, ":now": now })
+            })
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        let db = self.db.lock().unwrap();
+        let now = now();
+        TABLES
+            .iter()
+            .map(|(_, table)| {
+                let sql = format!("SELECT COUNT(*) FROM {} WHERE {}", table, LIVE);
+                db.conn
+                    .query_row(&sql, named_params! { ":now": now }, |row| row.get::<_, i64>(0))
+                    .unwrap_or_else(|e| {
+                        db.read_failed(&e);
+                        0
+                    }) as usize
+            })
+            .sum()
+    }
+
+    fn top_n(&self, prefix: &str, field: &str, limit: usize) -> Vec<Entry> {
+        let db = self.db.lock().unwrap();
+        let (table, column) = match (indexed_table(prefix), field) {
+            (Some("scores"), "score") => ("scores", "score"),
+            (Some("runs"), "score") => ("runs", "score"),
+            _ => {
+                drop(db);
+                return storage::scan_top_n(self.scan(prefix), field, limit);
+            }
+        };
+        let sql = format!(
+            "SELECT key, value, expires_at FROM {} WHERE substr(key, 1, length(:prefix)) = :prefix AND {} \
+             ORDER BY {} DESC, json_extract(value, '$.timestamp') ASC, key ASC LIMIT :limit",
+            table, LIVE, column,
+        );
+        db.query(&sql, named_params! {
+            ":prefix": prefix,
+            ":now": now(),
+            ":limit": limit.min(i64::MAX as usize) as i64,
+        })
+    }
+
+    fn find_by(&self, prefix: &str, field: &str, value: &str) -> Vec<Entry> {
+        let db = self.db.lock().unwrap();
+        let (table, column) = match (indexed_table(prefix), field) {
+            (Some("scores"), "player_id") => ("scores", "player_id"),
+            (Some("scores"), "name") => ("scores", "name"),
+            (Some("players"), "name") => ("players", "name"),
+            _ => {
+                drop(db);
+                return storage::scan_find_by(self.scan(prefix), field, value);
+            }
+        };
+        let sql = format!(
+            "SELECT key, value, expires_at FROM {} WHERE {} = :value AND substr(key, 1, length(:prefix)) = :prefix AND {}",
+            table, column, LIVE,
+        );
+        db.query(&sql, named_params! { ":value": value, ":prefix": prefix, ":now": now() })
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.db.lock().is_ok_and(|db| !db.read_failed.get())
+    }
+
+    fn flush(&self) -> Result<(), StorageError> {
+        let mut db = self.db.lock().map_err(|_| StorageError("store lock poisoned".to_string()))?;
+        db.purge_expired()?;
+        db.conn
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .map_err(sql_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+    use super::*;
+
+    #[test]
+    fn failed_read_marks_the_store_unhealthy() {
+        let path = std::env::temp_dir().join(format!("side_scroller-{}.db", Uuid::new_v4().simple()));
+        let store = SqliteStorage::open(&path).unwrap();
+        store.set("hiscore:all:ann-10-1", r#"{"name":"ann","score":10}"#, None).unwrap();
+        assert!(store.is_healthy());
+
+        store.db.lock().unwrap().conn.execute_batch("DROP TABLE scores").unwrap();
+
+        assert!(store.top_n("hiscore:all:", "score", 3).is_empty());
+        assert!(!store.is_healthy());
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -1,67 +1,618 @@
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
-use warp::Filter;
-use simple_kv_store::KvStore;
-use crate::core::Game;
-use crate::server::handlers;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{error, info};
+use uuid::Uuid;
+use warp::filters::BoxedFilter;
+use warp::http::Method;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+use crate::storage::Store;
+use crate::core::Player;
+use crate::server::{handlers, openapi};
+use crate::server::metrics::Metrics;
+use crate::server::race::{self, Rooms, RACER_IDLE_TIMEOUT};
+use crate::server::rate_limit::{rate_limit, RateLimitConfig, RateLimiter};
+use crate::server::session::{self, Games};
+use crate::server::snapshot;
+
+const DEFAULT_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5);
 
 pub struct GameServer {
-    store: Arc<Mutex<KvStore>>,
-    games: Arc<Mutex<HashMap<String, Game>>>,
+    store: Store,
+    games: Games,
+    rooms: Rooms,
+    metrics: Arc<Metrics>,
+    rate_limits: RateLimitConfig,
+    snapshot_interval: Duration,
+    // Flips to true once a shutdown signal arrives
+    shutdown: watch::Sender<bool>,
 }
 
 impl GameServer {
-    pub fn new(store: Arc<Mutex<KvStore>>) -> Self {
+    pub fn new(store: Store) -> Self {
         Self {
             store,
             games: Arc::new(Mutex::new(HashMap::new())),
+            rooms: Arc::new(Mutex::new(HashMap::new())),
+            metrics: Arc::new(Metrics::new()),
+            rate_limits: RateLimitConfig::default(),
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+            shutdown: watch::channel(false).0,
         }
     }
 
-    pub async fn run(&self, port: u16) {
+    pub fn with_rate_limits(mut self, rate_limits: RateLimitConfig) -> Self {
+        self.rate_limits = rate_limits;
+        self
+    }
+
+    pub fn with_snapshot_interval(mut self, snapshot_interval: Duration) -> Self {
+        self.snapshot_interval = snapshot_interval;
+        self
+    }
+
+    pub async fn run(&self, addr: SocketAddr) {
         let games = self.games.clone();
+        let metrics = self.metrics.clone();
+        let store = self.store.clone();
+
+        // Pick up sessions saved before the last restart
+        let mut restored = snapshot::load_sessions(&store);
+        if !restored.is_empty() {
+            info!(sessions = restored.len(), "restored game sessions");
+        }
+        for session in restored.values_mut() {
+            session.game.subscribe(metrics.subscriber());
+        }
+        games.lock().unwrap().extend(restored);
+
+        let snapshotter = {
+            let store = store.clone();
+            let games = games.clone();
+            let period = self.snapshot_interval;
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(period);
+                loop {
+                    interval.tick().await;
+                    snapshot::save_sessions(&store, &games);
+                    session::drop_idle(&games);
+                }
+            })
+        };
+
+        let room_sweeper = {
+            let rooms = self.rooms.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(RACER_IDLE_TIMEOUT);
+                loop {
+                    interval.tick().await;
+                    race::sweep_rooms(&rooms);
+                }
+            })
+        };
+
+        let routes = self.routes();
+        let templates: Vec<(Method, &'static str)> = routes
+            .iter()
+            .map(|route| (route.method.clone(), route.path))
+            .collect();
+        let routes = routes
+            .into_iter()
+            .map(|route| route.filter)
+            .reduce(|routes, next| routes.or(next).unify().boxed())
+            .expect("the server has routes")
+            .recover(handlers::handle_rejection)
+            .with(warp::cors().allow_any_origin().allow_header("authorization"))
+            .with(warp::log::custom(move |info| {
+                metrics.observe_request(
+                    info.method().as_str(),
+                    matched_route(&templates, info.method(), info.path()),
+                    info.elapsed().as_secs_f64(),
+                );
+                info!(
+                    status = info.status().as_u16(),
+                    elapsed_ms = info.elapsed().as_secs_f64() * 1000.0,
+                    "request completed"
+                );
+            }))
+            // One span per request; callers may supply their own X-Request-Id
+            .with(warp::trace(|info| {
+                let request_id = info.request_headers()
+                    .get("x-request-id")
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+                    .unwrap_or_else(|| Uuid::new_v4().to_string());
+                tracing::info_span!(
+                    "request",
+                    request_id = %request_id,
+                    method = %info.method(),
+                    path = %info.path(),
+                )
+            }));
+
+        let shutdown = self.shutdown.clone();
+        let (bound, server) = warp::serve(routes).bind_with_graceful_shutdown(addr, async move {
+            shutdown_signal().await;
+            info!("shutting down: refusing new games and finishing in-flight requests");
+            shutdown.send_replace(true);
+        });
+
+        info!(address = %bound, "game server running on http://{}{}", bound, openapi::API_BASE);
+        server.await;
+
+        snapshotter.abort();
+        room_sweeper.abort();
+        snapshot::save_sessions(&self.store, &self.games);
+        self.flush_store();
+        info!("game server stopped");
+    }
+
+    // Every route the server serves, in matching order. Paths are the full
+    // templates documented in the OpenAPI spec.
+    fn routes(&self) -> Vec<Route> {
         let store = self.store.clone();
+        let games = self.games.clone();
+        let rooms = self.rooms.clone();
+        let metrics = self.metrics.clone();
+
+        // Creating players and games is limited per minute, moves per second
+        let create_limiter = Arc::new(RateLimiter::new(
+            self.rate_limits.new_games_per_minute,
+            Duration::from_secs(60),
+        ));
+        let move_limiter = Arc::new(RateLimiter::new(
+            self.rate_limits.moves_per_second,
+            Duration::from_secs(1),
+        ));
+        let max_body_bytes = self.rate_limits.max_body_bytes;
+
+        // All routes live under /api/v1
+        let api = warp::path("api").and(warp::path("v1"));
+
+        let register = warp::post()
+            .and(api)
+            .and(warp::path("players"))
+            .and(warp::path::end())
+            .and(rate_limit(create_limiter.clone(), store.clone()))
+            .and(warp::body::content_length_limit(max_body_bytes))
+            .and(warp::body::json())
+            .and(with_store(store.clone()))
+            .and_then(handlers::register_player);
+
+        // Profiles are public so anyone can look a player up by name
+        let get_player = warp::get()
+            .and(api)
+            .and(warp::path("players"))
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(with_store(store.clone()))
+            .and_then(handlers::get_player);
+
+        let achievements = warp::get()
+            .and(api)
+            .and(warp::path("achievements"))
+            .and(warp::path::end())
+            .and_then(handlers::list_achievements);
 
-        // Routes
         let new_game = warp::post()
+            .and(api)
             .and(warp::path("game"))
             .and(warp::path("new"))
+            .and(warp::path::end())
+            .and(rate_limit(create_limiter.clone(), store.clone()))
+            .and(with_player(store.clone()))
             .and(with_store(store.clone()))
             .and(with_games(games.clone()))
+            .and(with_metrics(metrics.clone()))
+            .and(with_shutdown(self.shutdown.subscribe()))
             .and_then(handlers::new_game);
 
+        // One attempt per player per UTC day, on the day's shared course
+        let new_challenge = warp::post()
+            .and(api)
+            .and(warp::path("challenge"))
+            .and(warp::path::end())
+            .and(rate_limit(create_limiter.clone(), store.clone()))
+            .and(with_player(store.clone()))
+            .and(with_store(store.clone()))
+            .and(with_games(games.clone()))
+            .and(with_metrics(metrics.clone()))
+            .and(with_shutdown(self.shutdown.subscribe()))
+            .and_then(handlers::new_challenge);
+
+        let get_challenge = warp::get()
+            .and(api)
+            .and(warp::path("challenge"))
+            .and(warp::path::end())
+            .and(with_store(store.clone()))
+            .and_then(handlers::get_challenge);
+
         let get_state = warp::get()
+            .and(api)
             .and(warp::path("game"))
             .and(warp::path::param())
+            .and(warp::path::end())
+            .and(with_player(store.clone()))
             .and(with_games(games.clone()))
             .and_then(handlers::get_state);
 
         let make_move = warp::post()
+            .and(api)
             .and(warp::path("game"))
             .and(warp::path::param())
             .and(warp::path("move"))
+            .and(warp::path::end())
+            .and(rate_limit(move_limiter.clone(), store.clone()))
+            .and(with_player(store.clone()))
+            .and(warp::body::content_length_limit(max_body_bytes))
             .and(warp::body::json())
+            .and(with_store(store.clone()))
             .and(with_games(games.clone()))
+            .and(with_metrics(metrics.clone()))
             .and_then(handlers::make_move);
 
-        let routes = new_game
-            .or(get_state)
-            .or(make_move)
-            .with(warp::cors().allow_any_origin());
+        // Spectators need no token; the stream is read-only
+        let watch_game = warp::get()
+            .and(api)
+            .and(warp::path("game"))
+            .and(warp::path::param())
+            .and(warp::path("watch"))
+            .and(warp::path::end())
+            .and(with_games(games.clone()))
+            .and(with_shutdown(self.shutdown.subscribe()))
+            .and_then(handlers::watch_game);
+
+        let submit_score = warp::post()
+            .and(api)
+            .and(warp::path("game"))
+            .and(warp::path::param())
+            .and(warp::path("score"))
+            .and(warp::path::end())
+            .and(with_player(store.clone()))
+            .and(with_store(store.clone()))
+            .and(with_games(games.clone()))
+            .and(with_metrics(metrics.clone()))
+            .and_then(handlers::submit_score);
+
+        // Race rooms
+        let new_room = warp::post()
+            .and(api)
+            .and(warp::path("rooms"))
+            .and(warp::path::end())
+            .and(rate_limit(create_limiter.clone(), store.clone()))
+            .and(with_player(store.clone()))
+            .and(with_store(store.clone()))
+            .and(with_rooms(rooms.clone()))
+            .and(with_shutdown(self.shutdown.subscribe()))
+            .and_then(handlers::new_room);
+
+        let get_room = warp::get()
+            .and(api)
+            .and(warp::path("rooms"))
+            .and(warp::path::param())
+            .and(warp::path::end())
+            .and(with_player(store.clone()))
+            .and(with_rooms(rooms.clone()))
+            .and_then(handlers::get_room);
+
+        let join_room = warp::post()
+            .and(api)
+            .and(warp::path("rooms"))
+            .and(warp::path::param())
+            .and(warp::path("join"))
+            .and(warp::path::end())
+            .and(with_player(store.clone()))
+            .and(with_rooms(rooms.clone()))
+            .and_then(handlers::join_room);
+
+        let start_room = warp::post()
+            .and(api)
+            .and(warp::path("rooms"))
+            .and(warp::path::param())
+            .and(warp::path("start"))
+            .and(warp::path::end())
+            .and(with_player(store.clone()))
+            .and(with_rooms(rooms.clone()))
+            .and_then(handlers::start_room);
+
+        let race_move = warp::post()
+            .and(api)
+            .and(warp::path("rooms"))
+            .and(warp::path::param())
+            .and(warp::path("move"))
+            .and(warp::path::end())
+            .and(rate_limit(move_limiter.clone(), store.clone()))
+            .and(with_player(store.clone()))
+            .and(warp::body::content_length_limit(max_body_bytes))
+            .and(warp::body::json())
+            .and(with_rooms(rooms.clone()))
+            .and_then(handlers::race_move);
+
+        let hiscores = warp::get()
+            .and(api)
+            .and(warp::path("hiscores"))
+            .and(warp::path::end())
+            .and(warp::query::<handlers::HiScoresQuery>())
+            .and(with_store(store.clone()))
+            .and_then(handlers::get_hiscores);
+
+        // Prometheus scrape endpoint, outside the versioned API
+        let metrics_route = warp::get()
+            .and(warp::path("metrics"))
+            .and(warp::path::end())
+            .and(with_store(store.clone()))
+            .and(with_games(games.clone()))
+            .and(with_rooms(rooms.clone()))
+            .and(with_metrics(metrics.clone()))
+            .and_then(handlers::get_metrics);
+
+        // Liveness and readiness probes
+        let healthz = warp::get()
+            .and(warp::path("healthz"))
+            .and(warp::path::end())
+            .map(|| warp::reply::json(&serde_json::json!({ "status": "ok" })));
+
+        let readyz = warp::get()
+            .and(warp::path("readyz"))
+            .and(warp::path::end())
+            .and(with_store(store.clone()))
+            .and(with_shutdown(self.shutdown.subscribe()))
+            .and_then(handlers::readiness);
+
+        let spec = warp::get()
+            .and(api)
+            .and(warp::path("openapi.json"))
+            .and(warp::path::end())
+            .map(|| warp::reply::json(&openapi::spec()));
+
+        vec![
+            route(Method::POST, "/api/v1/players", register),
+            route(Method::GET, "/api/v1/players/{name}", get_player),
+            route(Method::GET, "/api/v1/achievements", achievements),
+            route(Method::POST, "/api/v1/game/new", new_game),
+            route(Method::POST, "/api/v1/challenge", new_challenge),
+            route(Method::GET, "/api/v1/challenge", get_challenge),
+            route(Method::GET, "/api/v1/game/{game_id}", get_state),
+            route(Method::POST, "/api/v1/game/{game_id}/move", make_move),
+            route(Method::GET, "/api/v1/game/{game_id}/watch", watch_game),
+            route(Method::POST, "/api/v1/game/{game_id}/score", submit_score),
+            route(Method::POST, "/api/v1/rooms", new_room),
+            route(Method::GET, "/api/v1/rooms/{room_id}", get_room),
+            route(Method::POST, "/api/v1/rooms/{room_id}/join", join_room),
+            route(Method::POST, "/api/v1/rooms/{room_id}/start", start_room),
+            route(Method::POST, "/api/v1/rooms/{room_id}/move", race_move),
+            route(Method::GET, "/api/v1/hiscores", hiscores),
+            route(Method::GET, "/api/v1/openapi.json", spec),
+            route(Method::GET, "/metrics", metrics_route),
+            route(Method::GET, "/healthz", healthz),
+            route(Method::GET, "/readyz", readyz),
+        ]
+    }
+
+    fn flush_store(&self) {
+        match self.store.flush() {
+            Ok(()) => info!(keys = self.store.len(), "store flushed"),
+            Err(e) => error!(error = %e, "failed to flush store"),
+        }
+    }
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
 
-        println!("Game server running on http://localhost:{}", port);
-        warp::serve(routes).run(([127, 0, 0, 1], port)).await;
+struct Route {
+    method: Method,
+    path: &'static str,
+    filter: BoxedFilter<(Response,)>,
+}
+
+fn route<F, R>(method: Method, path: &'static str, filter: F) -> Route
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply + 'static,
+{
+    Route {
+        method,
+        path,
+        filter: filter.map(Reply::into_response).boxed(),
     }
 }
 
+// The template of the route serving `method` on `path`. Placeholders such as
+// {game_id} match any one non-empty segment.
+fn matched_route(templates: &[(Method, &'static str)], method: &Method, path: &str) -> Option<&'static str> {
+    templates
+        .iter()
+        .find(|(route_method, template)| {
+            route_method == method
+                && template.split('/').count() == path.split('/').count()
+                && template.split('/').zip(path.split('/')).all(|(expected, segment)| {
+                    expected == segment || (expected.starts_with('{') && !segment.is_empty())
+                })
+        })
+        .map(|(_, template)| *template)
+}
+
 fn with_store(
-    store: Arc<Mutex<KvStore>>,
-) -> impl Filter<Extract = (Arc<Mutex<KvStore>>,), Error = std::convert::Infallible> + Clone {
+    store: Store,
+) -> impl Filter<Extract = (Store,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || store.clone())
 }
 
 fn with_games(
-    games: Arc<Mutex<HashMap<String, Game>>>,
-) -> impl Filter<Extract = (Arc<Mutex<HashMap<String, Game>>>,), Error = std::convert::Infallible> + Clone {
+    games: Games,
+) -> impl Filter<Extract = (Games,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || games.clone())
-} 
\ No newline at end of file
+}
+
+fn with_rooms(
+    rooms: Rooms,
+) -> impl Filter<Extract = (Rooms,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || rooms.clone())
+}
+
+fn with_metrics(
+    metrics: Arc<Metrics>,
+) -> impl Filter<Extract = (Arc<Metrics>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || metrics.clone())
+}
+
+fn with_shutdown(
+    shutdown: watch::Receiver<bool>,
+) -> impl Filter<Extract = (watch::Receiver<bool>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || shutdown.clone())
+}
+
+fn with_player(
+    store: Store,
+) -> impl Filter<Extract = (Player,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(with_store(store))
+        .and_then(handlers::authenticate)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use serde_json::{json, Value};
+    use warp::http::{self, StatusCode};
+    use warp::hyper::body::Bytes;
+    use crate::storage::MemoryStorage;
+    use super::*;
+
+    // (method, full path template) of every operation in the spec
+    fn documented() -> BTreeSet<(String, String)> {
+        let mut documented = BTreeSet::new();
+        for (path, item) in openapi::spec()["paths"].as_object().unwrap() {
+            // Paths that override the server URL are relative to the root
+            let base = if item.get("servers").is_some() { "" } else { openapi::API_BASE };
+            for method in item.as_object().unwrap().keys().filter(|key| *key != "servers") {
+                documented.insert((method.to_uppercase(), format!("{}{}", base, path)));
+            }
+        }
+        documented
+    }
+
+    fn server() -> GameServer {
+        GameServer::new(Arc::new(MemoryStorage::new()))
+    }
+
+    #[test]
+    fn spec_documents_exactly_the_served_routes() {
+        let served: BTreeSet<(String, String)> = server().routes()
+            .iter()
+            .map(|route| (route.method.to_string(), route.path.to_string()))
+            .collect();
+        let documented = documented();
+
+        let undocumented: Vec<_> = served.difference(&documented).collect();
+        assert!(undocumented.is_empty(), "routes missing from the spec: {:?}", undocumented);
+        let unserved: Vec<_> = documented.difference(&served).collect();
+        assert!(unserved.is_empty(), "documented routes the server doesn't serve: {:?}", unserved);
+    }
+
+    #[test]
+    fn requests_are_labelled_by_route_template() {
+        let templates: Vec<(Method, &'static str)> = server().routes()
+            .iter()
+            .map(|route| (route.method.clone(), route.path))
+            .collect();
+
+        let label = |method: Method, path: &str| matched_route(&templates, &method, path);
+        assert_eq!(label(Method::POST, "/api/v1/game/not-a-uuid/move"), Some("/api/v1/game/{game_id}/move"));
+        assert_eq!(label(Method::GET, "/api/v1/players/Someone"), Some("/api/v1/players/{name}"));
+        assert_eq!(label(Method::GET, "/metrics"), Some("/metrics"));
+        assert_eq!(label(Method::GET, "/api/v1/game/new/move"), None);
+        assert_eq!(label(Method::GET, "/api/v1/players/"), None);
+        assert_eq!(label(Method::DELETE, "/api/v1/players"), None);
+        assert_eq!(label(Method::GET, "/random/junk"), None);
+    }
+
+    async fn call(filter: &BoxedFilter<(Response,)>, method: &Method, path: &str, token: &str, body: Value) -> http::Response<Bytes> {
+        warp::test::request()
+            .method(method.as_str())
+            .path(path)
+            .header("authorization", format!("Bearer {}", token))
+            .json(&body)
+            .reply(&filter.clone().recover(handlers::handle_rejection))
+            .await
+    }
+
+    async fn call_json(filter: &BoxedFilter<(Response,)>, method: Method, path: &str, token: &str, body: Value) -> Value {
+        let response = call(filter, &method, path, token, body).await;
+        serde_json::from_slice(response.body()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_can_read_a_game() {
+        let all = server().routes()
+            .into_iter()
+            .map(|route| route.filter)
+            .reduce(|routes, next| routes.or(next).unify().boxed())
+            .unwrap();
+
+        let owner = call_json(&all, Method::POST, "/api/v1/players", "", json!({ "name": "Owner" })).await;
+        let owner_token = owner["token"].as_str().unwrap().to_string();
+        let other = call_json(&all, Method::POST, "/api/v1/players", "", json!({ "name": "Other" })).await;
+        let other_token = other["token"].as_str().unwrap().to_string();
+        let game = call_json(&all, Method::POST, "/api/v1/game/new", &owner_token, json!({})).await;
+        let path = format!("/api/v1/game/{}", game["game_id"].as_str().unwrap());
+
+        let owner_status = call(&all, &Method::GET, &path, &owner_token, json!({})).await.status();
+        assert_eq!(owner_status, StatusCode::OK);
+        let other_status = call(&all, &Method::GET, &path, &other_token, json!({})).await.status();
+        assert_eq!(other_status, StatusCode::FORBIDDEN);
+    }
+
+    // Each route's own filter must take a request for its documented method
+    // and path, with real ids filled in, so the table can't drift from the
+    // filters it labels
+    #[tokio::test]
+    async fn every_route_serves_its_documented_path() {
+        let server = server();
+        let all = server.routes()
+            .into_iter()
+            .map(|route| route.filter)
+            .reduce(|routes, next| routes.or(next).unify().boxed())
+            .unwrap();
+
+        let player = call_json(&all, Method::POST, "/api/v1/players", "", json!({ "name": "Spec" })).await;
+        let token = player["token"].as_str().unwrap().to_string();
+        let game = call_json(&all, Method::POST, "/api/v1/game/new", &token, json!({})).await;
+        let game_id = game["game_id"].as_str().unwrap().to_string();
+        let room = call_json(&all, Method::POST, "/api/v1/rooms", &token, json!({})).await;
+        let room_id = room["room_id"].as_str().unwrap().to_string();
+        // A finished game's watch stream ends after its first event
+        let path = format!("/api/v1/game/{}/move", game_id);
+        call_json(&all, Method::POST, &path, &token, json!({ "movement": "quit" })).await;
+
+        for route in server.routes() {
+            let path = route.path
+                .replace("{name}", "Spec")
+                .replace("{game_id}", &game_id)
+                .replace("{room_id}", &room_id);
+            let body = json!({ "name": "Other", "movement": "stay" });
+            let status = call(&route.filter, &route.method, &path, &token, body).await.status();
+            assert!(
+                status != StatusCode::NOT_FOUND && status != StatusCode::METHOD_NOT_ALLOWED,
+                "{} {} answered {}", route.method, path, status,
+            );
+        }
+    }
+}
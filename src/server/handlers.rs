@@ -1,10 +1,22 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::convert::Infallible;
+use std::sync::Arc;
+use futures_util::stream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
+use tracing::{debug, error, info, warn};
+use warp::http::{header, HeaderValue, StatusCode};
 use warp::{Reply, Rejection};
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
-use crate::core::{Game, PlayerMove};
-use simple_kv_store::KvStore;
+use crate::core::{
+    Achievement, AchievementManager, Board, Challenge, ChallengeError, ChallengeManager, Game, GameState, Placement,
+    Player, PlayerError, PlayerManager, PlayerMove, Profile, ProfileManager, ScoreManager, ACHIEVEMENTS,
+};
+use crate::server::metrics::Metrics;
+use crate::server::race::{RaceError, Room, Rooms};
+use crate::server::rate_limit::RateLimited;
+use crate::server::session::{GameSession, Games};
+use crate::storage::Store;
 
 #[derive(Serialize)]
 struct NewGameResponse {
@@ -16,54 +28,614 @@ pub struct MoveRequest {
     movement: String,
 }
 
+#[derive(Serialize)]
+struct NewChallengeResponse {
+    game_id: String,
+    date: String,
+    ends_at: u64,
+}
+
+#[derive(Serialize)]
+struct ChallengeResponse {
+    date: String,
+    ends_at: u64,
+    hiscores: Vec<HiScoreEntry>,
+}
+
+#[derive(Serialize)]
+struct NewRoomResponse {
+    room_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterRequest {
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct HiScoresQuery {
+    board: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RegisterResponse {
+    player_id: String,
+    name: String,
+    token: String,
+}
+
+#[derive(Serialize)]
+struct PlayerResponse {
+    player_id: String,
+    name: String,
+    stats: Profile,
+    average_score: f64,
+    longest_survival_secs: f64,
+    achievements: Vec<EarnedAchievement>,
+}
+
+#[derive(Serialize)]
+struct EarnedAchievement {
+    #[serde(flatten)]
+    achievement: &'static Achievement,
+    unlocked_at: u64,
+}
+
+#[derive(Serialize)]
+struct HiScoreEntry {
+    name: String,
+    score: u32,
+    expires_in: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct HiScoresResponse {
+    board: Board,
+    hiscores: Vec<HiScoreEntry>,
+    // Where a just-submitted score placed; empty when it made no board
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    placements: Vec<Placement>,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+pub async fn register_player(
+    req: RegisterRequest,
+    store: Store,
+) -> Result<impl Reply, Rejection> {
+    let players = PlayerManager::new(store);
+    match players.register(req.name.trim()) {
+        Ok((player, token)) => {
+            info!(player_id = %player.id, name = %player.name, "player registered");
+            let body = warp::reply::json(&RegisterResponse {
+                player_id: player.id,
+                name: player.name,
+                token,
+            });
+            Ok(warp::reply::with_status(body, StatusCode::CREATED))
+        }
+        Err(PlayerError::NameTaken) => Err(warp::reject::custom(Conflict(
+            PlayerError::NameTaken.to_string(),
+        ))),
+        Err(PlayerError::InvalidName) => Err(warp::reject::custom(InvalidName)),
+        Err(PlayerError::Store(e)) => {
+            error!(error = %e, "failed to register player");
+            Err(warp::reject::custom(StoreFailure))
+        }
+    }
+}
+
+pub async fn authenticate(
+    authorization: Option<String>,
+    store: Store,
+) -> Result<Player, Rejection> {
+    let token = authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| warp::reject::custom(Unauthorized))?;
+
+    PlayerManager::new(store)
+        .authenticate(token)
+        .ok_or_else(|| {
+            debug!("rejected unknown bearer token");
+            warp::reject::custom(Unauthorized)
+        })
+}
+
 pub async fn new_game(
-    store: Arc<Mutex<KvStore>>,
-    games: Arc<Mutex<HashMap<String, Game>>>,
+    player: Player,
+    store: Store,
+    games: Games,
+    metrics: Arc<Metrics>,
+    shutdown: watch::Receiver<bool>,
 ) -> Result<impl Reply, Rejection> {
+    if *shutdown.borrow() {
+        return Err(warp::reject::custom(ShuttingDown));
+    }
+
     let game_id = Uuid::new_v4().to_string();
-    let game = Game::new(store);
-    
-    games.lock().unwrap().insert(game_id.clone(), game);
-    
+    let mut game = Game::new(store);
+    game.subscribe(metrics.subscriber());
+
+    info!(game_id = %game_id, player_id = %player.id, "game started");
+    games.lock().unwrap().insert(game_id.clone(), GameSession::new(game, player.id));
+    metrics.game_started();
+
     Ok(warp::reply::json(&NewGameResponse { game_id }))
 }
 
+pub async fn new_challenge(
+    player: Player,
+    store: Store,
+    games: Games,
+    metrics: Arc<Metrics>,
+    shutdown: watch::Receiver<bool>,
+) -> Result<impl Reply, Rejection> {
+    if *shutdown.borrow() {
+        return Err(warp::reject::custom(ShuttingDown));
+    }
+
+    let challenge = Challenge::today();
+    ChallengeManager::new(store.clone())
+        .start(&player.id, &challenge)
+        .map_err(|e| match e {
+            ChallengeError::AlreadyPlayed => warp::reject::custom(Conflict(e.to_string())),
+            ChallengeError::Store(e) => {
+                error!(error = %e, "failed to start daily challenge");
+                warp::reject::custom(StoreFailure)
+            }
+        })?;
+
+    let game_id = Uuid::new_v4().to_string();
+    let mut game = Game::with_seed(store, challenge.seed);
+    game.subscribe(metrics.subscriber());
+    let mut session = GameSession::new(game, player.id.clone());
+    session.challenge = Some(challenge.clone());
+
+    info!(game_id = %game_id, player_id = %player.id, date = %challenge.date, "challenge game started");
+    games.lock().unwrap().insert(game_id.clone(), session);
+    metrics.game_started();
+
+    Ok(warp::reply::json(&NewChallengeResponse {
+        game_id,
+        date: challenge.date,
+        ends_at: challenge.ends_at,
+    }))
+}
+
+pub async fn get_challenge(store: Store) -> Result<impl Reply, Rejection> {
+    let challenge = Challenge::today();
+    let standings = ChallengeManager::new(store).standings(&challenge);
+    Ok(warp::reply::json(&ChallengeResponse {
+        date: challenge.date,
+        ends_at: challenge.ends_at,
+        hiscores: to_entries(standings),
+    }))
+}
+
 pub async fn get_state(
     game_id: String,
-    games: Arc<Mutex<HashMap<String, Game>>>,
+    player: Player,
+    games: Games,
 ) -> Result<impl Reply, Rejection> {
     let games = games.lock().unwrap();
-    if let Some(game) = games.get(&game_id) {
-        Ok(warp::reply::json(&game.get_state()))
-    } else {
-        Err(warp::reject::not_found())
+    let session = games.get(&game_id).ok_or_else(warp::reject::not_found)?;
+
+    if session.owner_id != player.id {
+        warn!(game_id = %game_id, player_id = %player.id, "state rejected: not the game owner");
+        return Err(warp::reject::custom(Forbidden));
     }
+
+    Ok(warp::reply::json(&session.game.get_state()))
 }
 
 pub async fn make_move(
     game_id: String,
+    player: Player,
     move_req: MoveRequest,
-    games: Arc<Mutex<HashMap<String, Game>>>,
+    store: Store,
+    games: Games,
+    metrics: Arc<Metrics>,
 ) -> Result<impl Reply, Rejection> {
-    let mut games = games.lock().unwrap();
-    
-    if let Some(game) = games.get_mut(&game_id) {
-        let movement = match move_req.movement.as_str() {
-            "up" => PlayerMove::Up,
-            "down" => PlayerMove::Down,
-            "quit" => PlayerMove::Quit,
-            _ => return Err(warp::reject::custom(InvalidMove)),
-        };
-        
-        game.handle_input(movement);
-        game.update();
-        
-        Ok(warp::reply::json(&game.get_state()))
+    let (state, ticks, finished, challenge) = {
+        let mut games = games.lock().unwrap();
+        let session = games.get_mut(&game_id).ok_or_else(warp::reject::not_found)?;
+
+        if session.owner_id != player.id {
+            warn!(game_id = %game_id, player_id = %player.id, "move rejected: not the game owner");
+            return Err(warp::reject::custom(Forbidden));
+        }
+
+        let was_over = session.game.get_state().is_game_over;
+        if let Some(movement) = parse_move(&move_req)? {
+            session.game.handle_input(movement);
+        }
+        session.game.update();
+        session.publish();
+        session.touch();
+
+        let state = session.game.get_state();
+        let finished = !was_over && state.is_game_over;
+        if finished && session.challenge.is_some() {
+            // Challenge results are recorded below, so the snapshot can go
+            session.score_submitted = true;
+        }
+        (state, session.game.tick(), finished, session.challenge.clone())
+    };
+
+    metrics.move_made();
+    if finished {
+        metrics.game_finished();
+        info!(game_id = %game_id, score = state.score, "game over");
+        // The games lock is released before touching the store
+        ProfileManager::new(store.clone()).record_run(&player.id, &state, ticks, None);
+        for achievement in AchievementManager::new(store.clone()).record(&player.id, &state.achievements) {
+            info!(game_id = %game_id, player_id = %player.id, achievement = achievement.id, "achievement unlocked");
+        }
+        if let Some(challenge) = challenge {
+            ChallengeManager::new(store).finish(&player, &challenge, &state.breakdown);
+        }
+    }
+
+    Ok(warp::reply::json(&state))
+}
+
+// Read-only Server-Sent Events stream of a game's state. The current state is
+// sent first, then one event per move until the game is over or the server
+// shuts down.
+pub async fn watch_game(
+    game_id: String,
+    games: Games,
+    shutdown: watch::Receiver<bool>,
+) -> Result<impl Reply, Rejection> {
+    let (initial, updates) = {
+        let games = games.lock().unwrap();
+        let session = games.get(&game_id).ok_or_else(warp::reject::not_found)?;
+        (session.game.get_state(), session.updates.subscribe())
+    };
+
+    let events = stream::unfold(
+        (Some(initial), updates, shutdown, false),
+        |(pending, mut updates, mut shutdown, finished)| async move {
+            if finished || *shutdown.borrow() {
+                return None;
+            }
+
+            let state: GameState = match pending {
+                Some(state) => state,
+                None => loop {
+                    tokio::select! {
+                        update = updates.recv() => match update {
+                            Ok(state) => break state,
+                            Err(RecvError::Lagged(_)) => continue,
+                            Err(RecvError::Closed) => return None,
+                        },
+                        _ = shutdown.changed() => return None,
+                    }
+                },
+            };
+
+            let finished = state.is_game_over;
+            let event = warp::sse::Event::default()
+                .event("state")
+                .json_data(&state)
+                .unwrap();
+            Some((Ok::<_, Infallible>(event), (None, updates, shutdown, finished)))
+        },
+    );
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)))
+}
+
+// "stay" advances the game without changing lanes
+fn parse_move(move_req: &MoveRequest) -> Result<Option<PlayerMove>, Rejection> {
+    match move_req.movement.as_str() {
+        "up" => Ok(Some(PlayerMove::Up)),
+        "down" => Ok(Some(PlayerMove::Down)),
+        "quit" => Ok(Some(PlayerMove::Quit)),
+        "stay" => Ok(None),
+        _ => Err(warp::reject::custom(InvalidMove)),
+    }
+}
+
+pub async fn submit_score(
+    game_id: String,
+    player: Player,
+    store: Store,
+    games: Games,
+    metrics: Arc<Metrics>,
+) -> Result<impl Reply, Rejection> {
+    let breakdown = {
+        let mut games = games.lock().unwrap();
+        let session = games.get_mut(&game_id).ok_or_else(warp::reject::not_found)?;
+
+        if session.owner_id != player.id {
+            return Err(warp::reject::custom(Forbidden));
+        }
+
+        if session.challenge.is_some() {
+            return Err(warp::reject::custom(Conflict(
+                "Daily challenge results are recorded when the game ends".to_string(),
+            )));
+        }
+
+        let state = session.game.get_state();
+        if !state.is_game_over {
+            return Err(warp::reject::custom(Conflict("Game is still running".to_string())));
+        }
+        if session.score_submitted {
+            return Err(warp::reject::custom(Conflict("Score was already submitted".to_string())));
+        }
+
+        session.score_submitted = true;
+        session.touch();
+        state.breakdown
+    };
+
+    // The games lock is released before touching the store
+    let scores = ScoreManager::new(store);
+    let score = breakdown.total();
+    let outcome = scores.submit_score(&player, &breakdown);
+    metrics.hiscore_submitted();
+    info!(game_id = %game_id, player_id = %player.id, score, placements = outcome.placements.len(), "score submitted");
+    let mut response = to_hiscores_response(scores.default_board(), outcome.hiscores);
+    response.placements = outcome.placements;
+    Ok(warp::reply::json(&response))
+}
+
+pub async fn readiness(
+    store: Store,
+    shutdown: watch::Receiver<bool>,
+) -> Result<impl Reply, Rejection> {
+    let (status, code) = if *shutdown.borrow() {
+        ("shutting_down", StatusCode::SERVICE_UNAVAILABLE)
+    } else if !store.is_healthy() {
+        ("store_unavailable", StatusCode::SERVICE_UNAVAILABLE)
     } else {
-        Err(warp::reject::not_found())
+        ("ready", StatusCode::OK)
+    };
+    let body = warp::reply::json(&serde_json::json!({ "status": status }));
+    Ok(warp::reply::with_status(body, code))
+}
+
+pub async fn get_metrics(
+    store: Store,
+    games: Games,
+    rooms: Rooms,
+    metrics: Arc<Metrics>,
+) -> Result<impl Reply, Rejection> {
+    let active_games = games
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|session| !session.game.get_state().is_game_over)
+        .count();
+    let active_rooms = rooms.lock().unwrap().len();
+    let store_keys = store.len();
+
+    let body = metrics.render(active_games, active_rooms, store_keys);
+    Ok(warp::reply::with_header(body, "content-type", "text/plain; version=0.0.4"))
+}
+
+pub async fn get_player(name: String, store: Store) -> Result<impl Reply, Rejection> {
+    let player = PlayerManager::new(store.clone())
+        .find_by_name(&name)
+        .ok_or_else(|| warp::reject::custom(UnknownPlayer))?;
+    let profile = ProfileManager::new(store.clone()).get(&player.id);
+    let achievements = AchievementManager::new(store)
+        .earned(&player.id)
+        .into_iter()
+        .filter_map(|(id, unlocked_at)| {
+            Achievement::by_id(&id).map(|achievement| EarnedAchievement { achievement, unlocked_at })
+        })
+        .collect();
+
+    Ok(warp::reply::json(&PlayerResponse {
+        player_id: player.id,
+        name: player.name,
+        average_score: profile.average_score(),
+        longest_survival_secs: profile.longest_survival_secs(),
+        stats: profile,
+        achievements,
+    }))
+}
+
+pub async fn list_achievements() -> Result<impl Reply, Rejection> {
+    Ok(warp::reply::json(&ACHIEVEMENTS))
+}
+
+pub async fn get_hiscores(query: HiScoresQuery, store: Store) -> Result<impl Reply, Rejection> {
+    let scores = ScoreManager::new(store);
+    let board = match query.board {
+        Some(name) => name.parse().map_err(|e| warp::reject::custom(InvalidBoard(e)))?,
+        None => scores.default_board(),
+    };
+    let hiscores = scores.top_scores(board);
+    Ok(warp::reply::json(&to_hiscores_response(board, hiscores)))
+}
+
+pub async fn new_room(
+    player: Player,
+    store: Store,
+    rooms: Rooms,
+    shutdown: watch::Receiver<bool>,
+) -> Result<impl Reply, Rejection> {
+    if *shutdown.borrow() {
+        return Err(warp::reject::custom(ShuttingDown));
+    }
+
+    let room_id = Uuid::new_v4().to_string();
+    let room = Room::new(room_id.clone(), player, store);
+
+    info!(room_id = %room_id, "race room opened");
+    rooms.lock().unwrap().insert(room_id.clone(), room);
+
+    Ok(warp::reply::json(&NewRoomResponse { room_id }))
+}
+
+pub async fn get_room(
+    room_id: String,
+    player: Player,
+    rooms: Rooms,
+) -> Result<impl Reply, Rejection> {
+    let mut rooms = rooms.lock().unwrap();
+    let room = rooms.get_mut(&room_id).ok_or_else(warp::reject::not_found)?;
+    room.forfeit_idle();
+
+    // Members see their own course and the other racers as ghosts
+    match room.race_view(&player.id) {
+        Some(view) => Ok(warp::reply::json(&view)),
+        None => Ok(warp::reply::json(&room.view())),
+    }
+}
+
+pub async fn join_room(
+    room_id: String,
+    player: Player,
+    rooms: Rooms,
+) -> Result<impl Reply, Rejection> {
+    let mut rooms = rooms.lock().unwrap();
+    let room = rooms.get_mut(&room_id).ok_or_else(warp::reject::not_found)?;
+
+    room.join(player).map_err(race_rejection)?;
+    Ok(warp::reply::json(&room.view()))
+}
+
+pub async fn start_room(
+    room_id: String,
+    player: Player,
+    rooms: Rooms,
+) -> Result<impl Reply, Rejection> {
+    let mut rooms = rooms.lock().unwrap();
+    let room = rooms.get_mut(&room_id).ok_or_else(warp::reject::not_found)?;
+
+    room.start(&player.id).map_err(race_rejection)?;
+    info!(room_id = %room_id, "race started");
+    Ok(warp::reply::json(&room.view()))
+}
+
+pub async fn race_move(
+    room_id: String,
+    player: Player,
+    move_req: MoveRequest,
+    rooms: Rooms,
+) -> Result<impl Reply, Rejection> {
+    let movement = parse_move(&move_req)?;
+    let mut rooms = rooms.lock().unwrap();
+    let room = rooms.get_mut(&room_id).ok_or_else(warp::reject::not_found)?;
+
+    room.make_move(&player.id, movement).map_err(race_rejection)?;
+    let view = room.race_view(&player.id).ok_or_else(|| warp::reject::custom(Forbidden))?;
+    Ok(warp::reply::json(&view))
+}
+
+fn race_rejection(err: RaceError) -> Rejection {
+    match err {
+        RaceError::NotJoined | RaceError::NotHost => warp::reject::custom(Forbidden),
+        _ => warp::reject::custom(Conflict(err.to_string())),
     }
 }
 
+fn to_hiscores_response(board: Board, hiscores: Vec<(String, u32, Option<u64>)>) -> HiScoresResponse {
+    HiScoresResponse {
+        board,
+        placements: Vec::new(),
+        hiscores: to_entries(hiscores),
+    }
+}
+
+fn to_entries(hiscores: Vec<(String, u32, Option<u64>)>) -> Vec<HiScoreEntry> {
+    hiscores
+        .into_iter()
+        .map(|(name, score, expires_in)| HiScoreEntry { name, score, expires_in })
+        .collect()
+}
+
 #[derive(Debug)]
 struct InvalidMove;
-impl warp::reject::Reject for InvalidMove {} 
\ No newline at end of file
+impl warp::reject::Reject for InvalidMove {}
+
+#[derive(Debug)]
+struct InvalidName;
+impl warp::reject::Reject for InvalidName {}
+
+// A plain not_found would lose out to the 405 from POST /players
+#[derive(Debug)]
+struct UnknownPlayer;
+impl warp::reject::Reject for UnknownPlayer {}
+
+#[derive(Debug)]
+struct InvalidBoard(String);
+impl warp::reject::Reject for InvalidBoard {}
+
+#[derive(Debug)]
+struct Unauthorized;
+impl warp::reject::Reject for Unauthorized {}
+
+#[derive(Debug)]
+struct Forbidden;
+impl warp::reject::Reject for Forbidden {}
+
+#[derive(Debug)]
+struct Conflict(String);
+impl warp::reject::Reject for Conflict {}
+
+#[derive(Debug)]
+struct ShuttingDown;
+impl warp::reject::Reject for ShuttingDown {}
+
+#[derive(Debug)]
+struct StoreFailure;
+impl warp::reject::Reject for StoreFailure {}
+
+pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
+    let mut retry_after = None;
+    let (status, message) = if err.is_not_found() {
+        (StatusCode::NOT_FOUND, "Not found".to_string())
+    } else if let Some(limited) = err.find::<RateLimited>() {
+        retry_after = Some(limited.retry_after);
+        (StatusCode::TOO_MANY_REQUESTS, "Too many requests".to_string())
+    } else if err.find::<ShuttingDown>().is_some() {
+        (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down".to_string())
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        (StatusCode::PAYLOAD_TOO_LARGE, "Request body too large".to_string())
+    } else if err.find::<warp::reject::LengthRequired>().is_some() {
+        (StatusCode::LENGTH_REQUIRED, "Content-Length header required".to_string())
+    } else if err.find::<Unauthorized>().is_some() {
+        (StatusCode::UNAUTHORIZED, "Missing or invalid bearer token".to_string())
+    } else if err.find::<Forbidden>().is_some() {
+        (StatusCode::FORBIDDEN, "Not allowed for this player".to_string())
+    } else if let Some(Conflict(message)) = err.find::<Conflict>() {
+        (StatusCode::CONFLICT, message.clone())
+    } else if err.find::<InvalidMove>().is_some() {
+        (StatusCode::BAD_REQUEST, "Invalid move, expected one of: up, down, stay, quit".to_string())
+    } else if err.find::<InvalidName>().is_some() {
+        (StatusCode::BAD_REQUEST, PlayerError::InvalidName.to_string())
+    } else if err.find::<UnknownPlayer>().is_some() {
+        (StatusCode::NOT_FOUND, "No player with that name".to_string())
+    } else if let Some(InvalidBoard(message)) = err.find::<InvalidBoard>() {
+        (StatusCode::BAD_REQUEST, message.clone())
+    } else if let Some(e) = err.find::<warp::filters::body::BodyDeserializeError>() {
+        (StatusCode::BAD_REQUEST, format!("Invalid request body: {}", e))
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        (StatusCode::METHOD_NOT_ALLOWED, "Method not allowed".to_string())
+    } else {
+        error!(rejection = ?err, "unhandled rejection");
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string())
+    };
+
+    let body = warp::reply::json(&ErrorResponse { error: message });
+    let mut response = warp::reply::with_status(body, status).into_response();
+    if status == StatusCode::UNAUTHORIZED {
+        response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+    if let Some(seconds) = retry_after {
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+    }
+    Ok(response)
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::core::{GameEvent, Subscriber};
+
+// Upper bounds in seconds of the request latency histogram buckets
+const LATENCY_BUCKETS: [f64; 11] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (i, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            if value <= *bound {
+                self.buckets[i] += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+// Counters and histograms exposed in the Prometheus text format on /metrics.
+// Gauges such as active games are sampled when the endpoint is scraped.
+#[derive(Default)]
+pub struct Metrics {
+    games_started: AtomicU64,
+    games_finished: AtomicU64,
+    moves: AtomicU64,
+    hiscore_submissions: AtomicU64,
+    pickups_collected: AtomicU64,
+    near_misses: AtomicU64,
+    // Indexed by lane, top then bottom
+    collisions: [AtomicU64; 2],
+    // Keyed by (method, route)
+    request_latency: Mutex<BTreeMap<(String, String), Histogram>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn game_started(&self) {
+        self.games_started.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn game_finished(&self) {
+        self.games_finished.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn move_made(&self) {
+        self.moves.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn hiscore_submitted(&self) {
+        self.hiscore_submissions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Counts the gameplay events of a game it is subscribed to
+    pub fn subscriber(self: &Arc<Self>) -> Box<dyn Subscriber> {
+        let metrics = self.clone();
+        Box::new(move |event: &GameEvent| metrics.game_event(event))
+    }
+
+    fn game_event(&self, event: &GameEvent) {
+        match *event {
+            GameEvent::PickupCollected { .. } => self.pickups_collected.fetch_add(1, Ordering::Relaxed),
+            GameEvent::NearMiss { .. } => self.near_misses.fetch_add(1, Ordering::Relaxed),
+            GameEvent::Collision { lane, .. } => self.collisions[lane].fetch_add(1, Ordering::Relaxed),
+            _ => return,
+        };
+    }
+
+    // `route` is the template of the route that served the request. Requests
+    // no route serves are folded together so arbitrary URLs can't create
+    // unbounded label values.
+    pub fn observe_request(&self, method: &str, route: Option<&str>, seconds: f64) {
+        self.request_latency
+            .lock()
+            .unwrap()
+            .entry((method.to_string(), route.unwrap_or("unmatched").to_string()))
+            .or_default()
+            .observe(seconds);
+    }
+
+    pub fn render(&self, active_games: usize, active_rooms: usize, store_keys: usize) -> String {
+        let mut out = String::new();
+
+        gauge(&mut out, "side_scroller_active_games", "Games currently in progress", active_games as u64);
+        gauge(&mut out, "side_scroller_active_rooms", "Race rooms currently held in memory", active_rooms as u64);
+        gauge(&mut out, "side_scroller_store_keys", "Number of keys in the KV store", store_keys as u64);
+        counter(&mut out, "side_scroller_games_started_total", "Games started",
+            self.games_started.load(Ordering::Relaxed));
+        counter(&mut out, "side_scroller_games_finished_total", "Games that reached game over",
+            self.games_finished.load(Ordering::Relaxed));
+        counter(&mut out, "side_scroller_moves_total", "Moves applied; use rate() for moves per second",
+            self.moves.load(Ordering::Relaxed));
+        counter(&mut out, "side_scroller_hiscore_submissions_total", "Scores submitted to the leaderboard",
+            self.hiscore_submissions.load(Ordering::Relaxed));
+        counter(&mut out, "side_scroller_pickups_collected_total", "Pickups collected",
+            self.pickups_collected.load(Ordering::Relaxed));
+        counter(&mut out, "side_scroller_near_misses_total", "Obstacles dodged at the last moment",
+            self.near_misses.load(Ordering::Relaxed));
+
+        let name = "side_scroller_collisions_total";
+        writeln!(out, "# HELP {} Collisions by lane", name).unwrap();
+        writeln!(out, "# TYPE {} counter", name).unwrap();
+        for (lane, count) in ["top", "bottom"].iter().zip(self.collisions.iter()) {
+            writeln!(out, "{}{{lane=\"{}\"}} {}", name, lane, count.load(Ordering::Relaxed)).unwrap();
+        }
+
+        let name = "side_scroller_request_duration_seconds";
+        writeln!(out, "# HELP {} HTTP request latency by route", name).unwrap();
+        writeln!(out, "# TYPE {} histogram", name).unwrap();
+        for ((method, route), histogram) in self.request_latency.lock().unwrap().iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", method, route);
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets.iter()) {
+                writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, count).unwrap();
+            }
+            writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, histogram.count).unwrap();
+            writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum).unwrap();
+            writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count).unwrap();
+        }
+
+        out
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} gauge", name).unwrap();
+    writeln!(out, "{} {}", name, value).unwrap();
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} counter", name).unwrap();
+    writeln!(out, "{} {}", name, value).unwrap();
+}
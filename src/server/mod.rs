@@ -1,4 +1,12 @@
 mod game_server;
 mod handlers;
+mod metrics;
+mod openapi;
+mod race;
+mod rate_limit;
+mod session;
+mod snapshot;
 
-pub use game_server::GameServer; 
\ No newline at end of file
+pub use game_server::GameServer;
+pub use openapi::API_BASE;
+pub use rate_limit::RateLimitConfig;
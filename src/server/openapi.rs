@@ -0,0 +1,739 @@
+use serde_json::{json, Value};
+
+pub const API_BASE: &str = "/api/v1";
+
+// OpenAPI 3 description of the routes registered in GameServer::routes.
+// Keep this in sync whenever a route or request/response type changes; the
+// server's tests fail when a route and its path here disagree.
+pub fn spec() -> Value {
+    let mut spec = json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Side Scroller Game API",
+            "version": "1.0.0",
+            "description": "Create side scroller games and play them one move at a time."
+        },
+        "servers": [{ "url": API_BASE }],
+        "security": [{ "bearerAuth": [] }],
+        "paths": {
+            "/players": {
+                "post": {
+                    "summary": "Register a player and receive an API token",
+                    "operationId": "registerPlayer",
+                    "security": [],
+                    "requestBody": json_body("RegisterRequest"),
+                    "responses": {
+                        "201": json_response("The new player and its bearer token", "RegisterResponse"),
+                        "400": json_response("Invalid name or request body", "ErrorResponse"),
+                        "409": json_response("Name already registered", "ErrorResponse"),
+                        "413": payload_too_large_response(),
+                        "429": rate_limited_response()
+                    }
+                }
+            },
+            "/players/{name}": {
+                "get": {
+                    "summary": "Get a player's lifetime statistics",
+                    "operationId": "getPlayer",
+                    "security": [],
+                    "parameters": [{
+                        "name": "name",
+                        "in": "path",
+                        "required": true,
+                        "schema": { "type": "string" }
+                    }],
+                    "responses": {
+                        "200": json_response("The player and their statistics", "PlayerResponse"),
+                        "404": json_response("Unknown player", "ErrorResponse")
+                    }
+                }
+            },
+            "/achievements": {
+                "get": {
+                    "summary": "List every achievement that can be earned",
+                    "operationId": "listAchievements",
+                    "security": [],
+                    "responses": {
+                        "200": {
+                            "description": "Achievement definitions",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "type": "array", "items": schema_ref("Achievement") }
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            "/game/new": {
+                "post": {
+                    "summary": "Start a new game owned by the caller",
+                    "operationId": "newGame",
+                    "responses": {
+                        "200": json_response("The id of the new game", "NewGameResponse"),
+                        "401": unauthorized_response(),
+                        "429": rate_limited_response(),
+                        "503": shutting_down_response()
+                    }
+                }
+            },
+            "/challenge": {
+                "get": {
+                    "summary": "Get today's daily challenge board",
+                    "operationId": "getChallenge",
+                    "security": [],
+                    "responses": {
+                        "200": json_response("The challenge date and its leaderboard", "ChallengeResponse")
+                    }
+                },
+                "post": {
+                    "summary": "Start the caller's one attempt at today's challenge",
+                    "description": "Everyone playing on the same UTC date gets the same course. The result goes to the challenge board when the game ends.",
+                    "operationId": "newChallenge",
+                    "responses": {
+                        "200": json_response("The challenge game", "NewChallengeResponse"),
+                        "401": unauthorized_response(),
+                        "409": json_response("Today's challenge was already played", "ErrorResponse"),
+                        "429": rate_limited_response(),
+                        "503": shutting_down_response()
+                    }
+                }
+            },
+            "/game/{game_id}": {
+                "get": {
+                    "summary": "Get the current state of a game",
+                    "operationId": "getState",
+                    "parameters": [game_id_parameter()],
+                    "responses": {
+                        "200": json_response("Current game state", "GameState"),
+                        "401": unauthorized_response(),
+                        "403": json_response("Game belongs to another player", "ErrorResponse"),
+                        "404": json_response("Unknown game id", "ErrorResponse")
+                    }
+                }
+            },
+            "/game/{game_id}/move": {
+                "post": {
+                    "summary": "Apply a move and advance the game by one tick",
+                    "operationId": "makeMove",
+                    "parameters": [game_id_parameter()],
+                    "requestBody": json_body("MoveRequest"),
+                    "responses": {
+                        "200": json_response("Game state after the move", "GameState"),
+                        "400": json_response("Invalid move or request body", "ErrorResponse"),
+                        "401": unauthorized_response(),
+                        "403": json_response("Game belongs to another player", "ErrorResponse"),
+                        "404": json_response("Unknown game id", "ErrorResponse"),
+                        "413": payload_too_large_response(),
+                        "429": rate_limited_response()
+                    }
+                }
+            },
+            "/game/{game_id}/watch": {
+                "get": {
+                    "summary": "Watch a game live as Server-Sent Events",
+                    "description": "Emits a `state` event with the current GameState, then one per move until the game is over.",
+                    "operationId": "watchGame",
+                    "security": [],
+                    "parameters": [game_id_parameter()],
+                    "responses": {
+                        "200": {
+                            "description": "Stream of `state` events whose data is a GameState",
+                            "content": {
+                                "text/event-stream": { "schema": schema_ref("GameState") }
+                            }
+                        },
+                        "404": json_response("Unknown game id", "ErrorResponse")
+                    }
+                }
+            },
+            "/game/{game_id}/score": {
+                "post": {
+                    "summary": "Submit the final score of a finished game to the leaderboard",
+                    "operationId": "submitScore",
+                    "parameters": [game_id_parameter()],
+                    "responses": {
+                        "200": json_response("Leaderboard after the submission", "HiScoresResponse"),
+                        "401": unauthorized_response(),
+                        "403": json_response("Game belongs to another player", "ErrorResponse"),
+                        "404": json_response("Unknown game id", "ErrorResponse"),
+                        "409": json_response("Game still running, score already submitted, or a daily challenge game", "ErrorResponse")
+                    }
+                }
+            },
+            "/rooms": {
+                "post": {
+                    "summary": "Open a race room; the caller becomes its host",
+                    "operationId": "newRoom",
+                    "responses": {
+                        "200": json_response("The id of the new room", "NewRoomResponse"),
+                        "401": unauthorized_response(),
+                        "429": rate_limited_response(),
+                        "503": shutting_down_response()
+                    }
+                }
+            },
+            "/rooms/{room_id}": {
+                "get": {
+                    "summary": "Get a room; members also receive their course and ghosts",
+                    "operationId": "getRoom",
+                    "parameters": [room_id_parameter()],
+                    "responses": {
+                        "200": {
+                            "description": "RaceView for members, RoomView for everyone else",
+                            "content": {
+                                "application/json": {
+                                    "schema": {
+                                        "oneOf": [schema_ref("RaceView"), schema_ref("RoomView")]
+                                    }
+                                }
+                            }
+                        },
+                        "401": unauthorized_response(),
+                        "404": json_response("Unknown room id", "ErrorResponse")
+                    }
+                }
+            },
+            "/rooms/{room_id}/join": {
+                "post": {
+                    "summary": "Join a waiting room",
+                    "operationId": "joinRoom",
+                    "parameters": [room_id_parameter()],
+                    "responses": {
+                        "200": json_response("Room after joining", "RoomView"),
+                        "401": unauthorized_response(),
+                        "404": json_response("Unknown room id", "ErrorResponse"),
+                        "409": json_response("Room full, already joined or already started", "ErrorResponse")
+                    }
+                }
+            },
+            "/rooms/{room_id}/start": {
+                "post": {
+                    "summary": "Start the race (host only)",
+                    "operationId": "startRoom",
+                    "parameters": [room_id_parameter()],
+                    "responses": {
+                        "200": json_response("Room after starting", "RoomView"),
+                        "401": unauthorized_response(),
+                        "403": json_response("Caller is not the host", "ErrorResponse"),
+                        "404": json_response("Unknown room id", "ErrorResponse"),
+                        "409": json_response("Not enough players or already started", "ErrorResponse")
+                    }
+                }
+            },
+            "/rooms/{room_id}/move": {
+                "post": {
+                    "summary": "Apply a move to the caller's race and advance it by one tick",
+                    "description": "A racer who sends no moves for 15 seconds forfeits and is ranked by their score so far.",
+                    "operationId": "raceMove",
+                    "parameters": [room_id_parameter()],
+                    "requestBody": json_body("MoveRequest"),
+                    "responses": {
+                        "200": json_response("Caller's view of the race", "RaceView"),
+                        "400": json_response("Invalid move or request body", "ErrorResponse"),
+                        "401": unauthorized_response(),
+                        "403": json_response("Caller is not in this room", "ErrorResponse"),
+                        "404": json_response("Unknown room id", "ErrorResponse"),
+                        "409": json_response("Race is not running", "ErrorResponse"),
+                        "413": payload_too_large_response(),
+                        "429": rate_limited_response()
+                    }
+                }
+            },
+            "/hiscores": {
+                "get": {
+                    "summary": "Get a leaderboard",
+                    "operationId": "getHiScores",
+                    "security": [],
+                    "parameters": [{
+                        "name": "board",
+                        "in": "query",
+                        "required": false,
+                        "description": "Leaderboard to return; defaults to rolling, or the first enabled board",
+                        "schema": { "$ref": "#/components/schemas/Board" }
+                    }],
+                    "responses": {
+                        "200": json_response("Top scores", "HiScoresResponse"),
+                        "400": json_response("Unknown board", "ErrorResponse")
+                    }
+                }
+            },
+            "/openapi.json": {
+                "get": {
+                    "summary": "This OpenAPI document",
+                    "operationId": "getOpenApi",
+                    "security": [],
+                    "responses": {
+                        "200": {
+                            "description": "OpenAPI 3 document",
+                            "content": { "application/json": { "schema": { "type": "object" } } }
+                        }
+                    }
+                }
+            }
+        },
+        "components": {
+            "securitySchemes": {
+                "bearerAuth": { "type": "http", "scheme": "bearer" }
+            },
+            "schemas": schemas()
+        }
+    });
+    if let (Some(paths), Value::Object(unversioned)) = (spec["paths"].as_object_mut(), unversioned_paths()) {
+        paths.extend(unversioned);
+    }
+    spec
+}
+
+// Metrics and probes live at the root rather than under API_BASE, so each
+// overrides the server URL
+fn unversioned_paths() -> Value {
+    let root = json!([{ "url": "/" }]);
+    json!({
+        "/metrics": {
+            "servers": root,
+            "get": {
+                "summary": "Prometheus metrics",
+                "operationId": "getMetrics",
+                "security": [],
+                "responses": {
+                    "200": {
+                        "description": "Metrics in the Prometheus text format",
+                        "content": { "text/plain": { "schema": { "type": "string" } } }
+                    }
+                }
+            }
+        },
+        "/healthz": {
+            "servers": root,
+            "get": {
+                "summary": "Liveness probe",
+                "operationId": "getHealth",
+                "security": [],
+                "responses": {
+                    "200": json_response("The process is up", "ProbeStatus")
+                }
+            }
+        },
+        "/readyz": {
+            "servers": root,
+            "get": {
+                "summary": "Readiness probe",
+                "description": "Fails while the store is unavailable or the server is shutting down.",
+                "operationId": "getReadiness",
+                "security": [],
+                "responses": {
+                    "200": json_response("Ready to serve", "ProbeStatus"),
+                    "503": json_response("Store unavailable or shutting down", "ProbeStatus")
+                }
+            }
+        }
+    })
+}
+
+// Request and response bodies referenced from the paths above
+fn schemas() -> Value {
+    json!({
+        "RegisterRequest": {
+            "type": "object",
+            "required": ["name"],
+            "properties": {
+                "name": { "type": "string", "pattern": "^[A-Za-z]{1,16}$" }
+            }
+        },
+        "RegisterResponse": {
+            "type": "object",
+            "required": ["player_id", "name", "token"],
+            "properties": {
+                "player_id": { "type": "string", "format": "uuid" },
+                "name": { "type": "string" },
+                "token": { "type": "string" }
+            }
+        },
+        "Board": {
+            "type": "string",
+            "enum": ["all-time", "daily", "weekly", "rolling"]
+        },
+        "Achievement": {
+            "type": "object",
+            "required": ["id", "name", "description"],
+            "properties": {
+                "id": { "type": "string" },
+                "name": { "type": "string" },
+                "description": { "type": "string" }
+            }
+        },
+        "NewChallengeResponse": {
+            "type": "object",
+            "required": ["game_id", "date", "ends_at"],
+            "properties": {
+                "game_id": { "type": "string", "format": "uuid" },
+                "date": { "type": "string", "format": "date" },
+                "ends_at": { "description": "Unix timestamp of the next midnight UTC", "type": "integer" }
+            }
+        },
+        "ChallengeResponse": {
+            "type": "object",
+            "required": ["date", "ends_at", "hiscores"],
+            "properties": {
+                "date": { "type": "string", "format": "date" },
+                "ends_at": { "description": "Unix timestamp of the next midnight UTC", "type": "integer" },
+                "hiscores": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["name", "score"],
+                        "properties": {
+                            "name": { "type": "string" },
+                            "score": { "type": "integer", "minimum": 0 },
+                            "expires_in": { "type": "integer", "nullable": true }
+                        }
+                    }
+                }
+            }
+        },
+        "PlayerResponse": {
+            "type": "object",
+            "required": ["player_id", "name", "stats", "average_score", "longest_survival_secs", "achievements"],
+            "properties": {
+                "player_id": { "type": "string", "format": "uuid" },
+                "name": { "type": "string" },
+                "average_score": { "type": "number" },
+                "longest_survival_secs": { "type": "number" },
+                "achievements": {
+                    "description": "Achievements the player has earned",
+                    "type": "array",
+                    "items": {
+                        "allOf": [
+                            schema_ref("Achievement"),
+                            {
+                                "type": "object",
+                                "required": ["unlocked_at"],
+                                "properties": {
+                                    "unlocked_at": { "description": "Unix timestamp", "type": "integer" }
+                                }
+                            }
+                        ]
+                    }
+                },
+                "stats": {
+                    "type": "object",
+                    "required": [
+                        "games_played", "total_score", "longest_survival_ticks",
+                        "collisions", "pickups_collected"
+                    ],
+                    "properties": {
+                        "games_played": { "type": "integer", "minimum": 0 },
+                        "total_score": { "type": "integer", "minimum": 0 },
+                        "best_run": {
+                            "type": "object",
+                            "nullable": true,
+                            "required": ["score", "ticks", "pickups", "recorded_at"],
+                            "properties": {
+                                "score": { "type": "integer", "minimum": 0 },
+                                "ticks": { "type": "integer", "minimum": 0 },
+                                "pickups": { "type": "integer", "minimum": 0 },
+                                "recorded_at": { "description": "Unix timestamp", "type": "integer" },
+                                "replay_id": { "type": "string" }
+                            }
+                        },
+                        "longest_survival_ticks": { "type": "integer", "minimum": 0 },
+                        "collisions": {
+                            "description": "Obstacles hit in the top and bottom lane",
+                            "type": "array",
+                            "items": { "type": "integer", "minimum": 0 },
+                            "minItems": 2,
+                            "maxItems": 2
+                        },
+                        "pickups_collected": { "type": "integer", "minimum": 0 }
+                    }
+                }
+            }
+        },
+        "HiScoresResponse": {
+            "type": "object",
+            "required": ["board", "hiscores"],
+            "properties": {
+                "board": { "$ref": "#/components/schemas/Board" },
+                "placements": {
+                    "description": "Boards a just-submitted score made it onto; omitted when it made none",
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["board", "rank"],
+                        "properties": {
+                            "board": { "$ref": "#/components/schemas/Board" },
+                            "rank": { "type": "integer", "minimum": 1 }
+                        }
+                    }
+                },
+                "hiscores": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["name", "score"],
+                        "properties": {
+                            "name": { "type": "string" },
+                            "score": { "type": "integer", "minimum": 0 },
+                            "expires_in": {
+                                "description": "Seconds until the entry expires",
+                                "type": "integer",
+                                "nullable": true
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        "NewRoomResponse": {
+            "type": "object",
+            "required": ["room_id"],
+            "properties": {
+                "room_id": { "type": "string", "format": "uuid" }
+            }
+        },
+        "RoomView": {
+            "type": "object",
+            "required": ["room_id", "status", "host_id", "players"],
+            "properties": {
+                "room_id": { "type": "string", "format": "uuid" },
+                "status": { "type": "string", "enum": ["waiting", "running", "finished"] },
+                "host_id": { "type": "string", "format": "uuid" },
+                "players": {
+                    "type": "array",
+                    "minItems": 1,
+                    "maxItems": 8,
+                    "items": {
+                        "type": "object",
+                        "required": ["player_id", "name", "score", "alive", "forfeited"],
+                        "properties": {
+                            "player_id": { "type": "string", "format": "uuid" },
+                            "name": { "type": "string" },
+                            "score": { "type": "integer", "minimum": 0 },
+                            "alive": { "type": "boolean" },
+                            "forfeited": {
+                                "description": "Left the race after sending no moves for 15 seconds",
+                                "type": "boolean"
+                            }
+                        }
+                    }
+                },
+                "ranking": {
+                    "description": "Final ranking, present once every racer has crashed",
+                    "type": "array",
+                    "nullable": true,
+                    "items": {
+                        "type": "object",
+                        "required": ["rank", "player_id", "name", "score"],
+                        "properties": {
+                            "rank": { "type": "integer", "minimum": 1 },
+                            "player_id": { "type": "string", "format": "uuid" },
+                            "name": { "type": "string" },
+                            "score": { "type": "integer", "minimum": 0 }
+                        }
+                    }
+                }
+            }
+        },
+        "RaceView": {
+            "type": "object",
+            "required": ["room", "state", "ghosts"],
+            "properties": {
+                "room": schema_ref("RoomView"),
+                "state": schema_ref("GameState"),
+                "ghosts": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["name", "lane", "score", "alive"],
+                        "properties": {
+                            "name": { "type": "string" },
+                            "lane": { "type": "integer", "enum": [0, 1] },
+                            "column": {
+                                "description": "Column in the caller's view, null when off screen",
+                                "type": "integer",
+                                "nullable": true
+                            },
+                            "score": { "type": "integer", "minimum": 0 },
+                            "alive": { "type": "boolean" }
+                        }
+                    }
+                }
+            }
+        },
+        "MoveRequest": {
+            "type": "object",
+            "required": ["movement"],
+            "properties": {
+                "movement": {
+                    "type": "string",
+                    "description": "`stay` advances one tick without changing lanes",
+                    "enum": ["up", "down", "stay", "quit"]
+                }
+            }
+        },
+        "NewGameResponse": {
+            "type": "object",
+            "required": ["game_id"],
+            "properties": {
+                "game_id": { "type": "string", "format": "uuid" }
+            }
+        },
+        "GameState": {
+            "type": "object",
+            "required": ["player_pos", "top_row", "bottom_row", "score", "is_game_over"],
+            "properties": {
+                "player_pos": {
+                    "description": "Player column and lane (0 = top, 1 = bottom)",
+                    "type": "array",
+                    "items": { "type": "integer", "minimum": 0 },
+                    "minItems": 2,
+                    "maxItems": 2
+                },
+                "top_row": {
+                    "description": "Obstacles in the top lane, one entry per column",
+                    "type": "array",
+                    "items": { "type": "boolean" }
+                },
+                "bottom_row": {
+                    "description": "Obstacles in the bottom lane, one entry per column",
+                    "type": "array",
+                    "items": { "type": "boolean" }
+                },
+                "score": { "type": "integer", "minimum": 0 },
+                "is_game_over": { "type": "boolean" },
+                "top_pickups": {
+                    "description": "Pickups in the top lane, one entry per column",
+                    "type": "array",
+                    "items": { "type": "boolean" }
+                },
+                "bottom_pickups": {
+                    "description": "Pickups in the bottom lane, one entry per column",
+                    "type": "array",
+                    "items": { "type": "boolean" }
+                },
+                "pickups": {
+                    "description": "Pickups collected this run. They add nothing to the score.",
+                    "type": "integer",
+                    "minimum": 0
+                },
+                "collisions": {
+                    "description": "Obstacles hit in the top and bottom lane",
+                    "type": "array",
+                    "items": { "type": "integer", "minimum": 0 },
+                    "minItems": 2,
+                    "maxItems": 2
+                },
+                "achievements": {
+                    "description": "Ids of the achievements reached this run, in order; saved to the player's profile when the game ends",
+                    "type": "array",
+                    "items": { "type": "string" }
+                },
+                "breakdown": schema_ref("ScoreBreakdown"),
+                "combo": {
+                    "description": "Near misses in a row. Each one is worth 10 points times the combo, up to x5, and the combo drops after 15 ticks without one.",
+                    "type": "object",
+                    "properties": {
+                        "count": { "type": "integer", "minimum": 0 },
+                        "ticks_left": { "description": "Ticks before the combo drops", "type": "integer", "minimum": 0 }
+                    }
+                }
+            }
+        },
+        "ScoreBreakdown": {
+            "description": "How the score was made up; score is distance plus bonus. Saved with the high score.",
+            "type": "object",
+            "properties": {
+                "distance": { "description": "One point per tick survived", "type": "integer", "minimum": 0 },
+                "near_misses": {
+                    "description": "Obstacles passed in the adjacent lane within one column of the player",
+                    "type": "integer",
+                    "minimum": 0
+                },
+                "bonus": { "description": "Points from near misses, multipliers included", "type": "integer", "minimum": 0 },
+                "combo_peak": { "description": "Longest combo of near misses", "type": "integer", "minimum": 0 }
+            }
+        },
+        "ProbeStatus": {
+            "type": "object",
+            "required": ["status"],
+            "properties": {
+                "status": { "type": "string", "enum": ["ok", "ready", "store_unavailable", "shutting_down"] }
+            }
+        },
+        "ErrorResponse": {
+            "type": "object",
+            "required": ["error"],
+            "properties": {
+                "error": { "type": "string" }
+            }
+        }
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn json_body(schema: &str) -> Value {
+    json!({
+        "required": true,
+        "content": {
+            "application/json": { "schema": schema_ref(schema) }
+        }
+    })
+}
+
+fn unauthorized_response() -> Value {
+    json_response("Missing or invalid bearer token", "ErrorResponse")
+}
+
+fn shutting_down_response() -> Value {
+    json_response("Server is shutting down and not accepting new games", "ErrorResponse")
+}
+
+fn payload_too_large_response() -> Value {
+    json_response("Request body exceeds the configured size limit", "ErrorResponse")
+}
+
+fn rate_limited_response() -> Value {
+    json!({
+        "description": "Rate limit exceeded for this client",
+        "headers": {
+            "Retry-After": {
+                "description": "Seconds to wait before retrying",
+                "schema": { "type": "integer" }
+            }
+        },
+        "content": {
+            "application/json": { "schema": schema_ref("ErrorResponse") }
+        }
+    })
+}
+
+fn json_response(description: &str, schema: &str) -> Value {
+    json!({
+        "description": description,
+        "content": {
+            "application/json": { "schema": schema_ref(schema) }
+        }
+    })
+}
+
+fn room_id_parameter() -> Value {
+    json!({
+        "name": "room_id",
+        "in": "path",
+        "required": true,
+        "schema": { "type": "string", "format": "uuid" }
+    })
+}
+
+fn game_id_parameter() -> Value {
+    json!({
+        "name": "game_id",
+        "in": "path",
+        "required": true,
+        "schema": { "type": "string", "format": "uuid" }
+    })
+}
@@ -0,0 +1,358 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use rand::Rng;
+use serde::Serialize;
+use tracing::info;
+use crate::storage::Store;
+use crate::core::{Game, GameState, Player, PlayerMove};
+use crate::GAME_WIDTH;
+
+pub const MIN_PLAYERS: usize = 2;
+pub const MAX_PLAYERS: usize = 8;
+// A racer who sends no moves for this long forfeits, so one idle client
+// can't keep the race from finishing
+pub const RACER_IDLE_TIMEOUT: Duration = Duration::from_secs(15);
+// Rooms nobody has touched for this long are dropped
+const ROOM_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+pub type Rooms = Arc<Mutex<HashMap<String, Room>>>;
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomStatus {
+    Waiting,
+    Running,
+    Finished,
+}
+
+#[derive(Debug)]
+pub enum RaceError {
+    RoomFull,
+    AlreadyJoined,
+    NotJoined,
+    NotHost,
+    NotEnoughPlayers,
+    AlreadyStarted,
+    NotRunning,
+}
+
+impl fmt::Display for RaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RaceError::RoomFull => write!(f, "Room is full ({} players max)", MAX_PLAYERS),
+            RaceError::AlreadyJoined => write!(f, "Already joined this room"),
+            RaceError::NotJoined => write!(f, "Not a member of this room"),
+            RaceError::NotHost => write!(f, "Only the host can start the race"),
+            RaceError::NotEnoughPlayers => write!(f, "At least {} players are needed", MIN_PLAYERS),
+            RaceError::AlreadyStarted => write!(f, "Race has already started"),
+            RaceError::NotRunning => write!(f, "Race is not running"),
+        }
+    }
+}
+
+struct Racer {
+    player: Player,
+    game: Game,
+    // Position in which this racer crashed or forfeited (0 = first out)
+    finish_order: Option<usize>,
+    forfeited: bool,
+    last_move: Instant,
+}
+
+impl Racer {
+    fn is_alive(&self) -> bool {
+        self.finish_order.is_none()
+    }
+}
+
+#[derive(Serialize)]
+pub struct RacerSummary {
+    pub player_id: String,
+    pub name: String,
+    pub score: u32,
+    pub alive: bool,
+    pub forfeited: bool,
+}
+
+#[derive(Serialize)]
+pub struct RankingEntry {
+    pub rank: usize,
+    pub player_id: String,
+    pub name: String,
+    pub score: u32,
+}
+
+#[derive(Serialize)]
+pub struct RoomView {
+    pub room_id: String,
+    pub status: RoomStatus,
+    pub host_id: String,
+    pub players: Vec<RacerSummary>,
+    pub ranking: Option<Vec<RankingEntry>>,
+}
+
+// Another racer drawn in the caller's view. `column` is where the ghost sits
+// on the caller's screen, or None when it is too far ahead or behind.
+#[derive(Serialize)]
+pub struct Ghost {
+    pub name: String,
+    pub lane: usize,
+    pub column: Option<usize>,
+    pub score: u32,
+    pub alive: bool,
+}
+
+#[derive(Serialize)]
+pub struct RaceView {
+    pub room: RoomView,
+    pub state: GameState,
+    pub ghosts: Vec<Ghost>,
+}
+
+// Forfeits idle racers in every room, even ones nobody is polling, and drops
+// rooms that have been abandoned
+pub fn sweep_rooms(rooms: &Rooms) {
+    let mut rooms = rooms.lock().unwrap();
+    for room in rooms.values_mut() {
+        room.forfeit_idle();
+    }
+    rooms.retain(|room_id, room| {
+        if room.is_abandoned() {
+            info!(room_id = %room_id, "dropped abandoned room");
+        }
+        !room.is_abandoned()
+    });
+}
+
+pub struct Room {
+    id: String,
+    host_id: String,
+    seed: u64,
+    status: RoomStatus,
+    racers: Vec<Racer>,
+    finished: usize,
+    store: Store,
+    last_activity: Instant,
+}
+
+impl Room {
+    pub fn new(id: String, host: Player, store: Store) -> Self {
+        let seed = rand::thread_rng().gen();
+        let mut room = Room {
+            id,
+            host_id: host.id.clone(),
+            seed,
+            status: RoomStatus::Waiting,
+            racers: Vec::new(),
+            finished: 0,
+            store,
+            last_activity: Instant::now(),
+        };
+        room.add_racer(host);
+        room
+    }
+
+    fn add_racer(&mut self, player: Player) {
+        // Every racer gets the room's seed, so all courses are identical
+        let game = Game::with_seed(self.store.clone(), self.seed);
+        self.racers.push(Racer {
+            player,
+            game,
+            finish_order: None,
+            forfeited: false,
+            last_move: Instant::now(),
+        });
+    }
+
+    fn racer_index(&self, player_id: &str) -> Option<usize> {
+        self.racers.iter().position(|r| r.player.id == player_id)
+    }
+
+    pub fn is_member(&self, player_id: &str) -> bool {
+        self.racer_index(player_id).is_some()
+    }
+
+    pub fn join(&mut self, player: Player) -> Result<(), RaceError> {
+        if self.status != RoomStatus::Waiting {
+            return Err(RaceError::AlreadyStarted);
+        }
+        if self.is_member(&player.id) {
+            return Err(RaceError::AlreadyJoined);
+        }
+        if self.racers.len() >= MAX_PLAYERS {
+            return Err(RaceError::RoomFull);
+        }
+        self.add_racer(player);
+        self.last_activity = Instant::now();
+        Ok(())
+    }
+
+    pub fn start(&mut self, player_id: &str) -> Result<(), RaceError> {
+        if player_id != self.host_id {
+            return Err(RaceError::NotHost);
+        }
+        if self.status != RoomStatus::Waiting {
+            return Err(RaceError::AlreadyStarted);
+        }
+        if self.racers.len() < MIN_PLAYERS {
+            return Err(RaceError::NotEnoughPlayers);
+        }
+        self.status = RoomStatus::Running;
+        // Time spent waiting in the lobby doesn't count as idling
+        let now = Instant::now();
+        for racer in &mut self.racers {
+            racer.last_move = now;
+        }
+        self.last_activity = now;
+        Ok(())
+    }
+
+    pub fn make_move(&mut self, player_id: &str, movement: Option<PlayerMove>) -> Result<(), RaceError> {
+        let index = self.racer_index(player_id).ok_or(RaceError::NotJoined)?;
+        if self.status != RoomStatus::Running {
+            return Err(RaceError::NotRunning);
+        }
+
+        let racer = &mut self.racers[index];
+        if !racer.is_alive() {
+            return Ok(());
+        }
+
+        racer.last_move = Instant::now();
+        self.last_activity = racer.last_move;
+        if let Some(movement) = movement {
+            racer.game.handle_input(movement);
+        }
+        racer.game.update();
+
+        if racer.game.get_state().is_game_over {
+            self.finish(index);
+        }
+        self.forfeit_idle();
+        Ok(())
+    }
+
+    fn finish(&mut self, index: usize) {
+        self.racers[index].finish_order = Some(self.finished);
+        self.finished += 1;
+        if self.finished == self.racers.len() {
+            self.status = RoomStatus::Finished;
+        }
+    }
+
+    // Takes racers who stopped sending moves out of a running race
+    pub fn forfeit_idle(&mut self) {
+        if self.status != RoomStatus::Running {
+            return;
+        }
+        for index in 0..self.racers.len() {
+            let racer = &mut self.racers[index];
+            if racer.is_alive() && racer.last_move.elapsed() > RACER_IDLE_TIMEOUT {
+                racer.forfeited = true;
+                info!(room_id = %self.id, player_id = %racer.player.id, "racer forfeited after going idle");
+                self.finish(index);
+            }
+        }
+    }
+
+    fn is_abandoned(&self) -> bool {
+        self.last_activity.elapsed() > ROOM_IDLE_TIMEOUT
+    }
+
+    fn ranking(&self) -> Vec<RankingEntry> {
+        let mut order: Vec<&Racer> = self.racers.iter().collect();
+        // Highest score first; on equal scores whoever crashed later ranks higher
+        order.sort_by(|a, b| {
+            b.game.get_state().score.cmp(&a.game.get_state().score)
+                .then(b.finish_order.cmp(&a.finish_order))
+        });
+        order
+            .into_iter()
+            .enumerate()
+            .map(|(i, racer)| RankingEntry {
+                rank: i + 1,
+                player_id: racer.player.id.clone(),
+                name: racer.player.name.clone(),
+                score: racer.game.get_state().score,
+            })
+            .collect()
+    }
+
+    pub fn view(&self) -> RoomView {
+        RoomView {
+            room_id: self.id.clone(),
+            status: self.status,
+            host_id: self.host_id.clone(),
+            players: self.racers
+                .iter()
+                .map(|racer| RacerSummary {
+                    player_id: racer.player.id.clone(),
+                    name: racer.player.name.clone(),
+                    score: racer.game.get_state().score,
+                    alive: racer.is_alive(),
+                    forfeited: racer.forfeited,
+                })
+                .collect(),
+            ranking: (self.status == RoomStatus::Finished).then(|| self.ranking()),
+        }
+    }
+
+    pub fn race_view(&self, player_id: &str) -> Option<RaceView> {
+        let me = &self.racers[self.racer_index(player_id)?];
+        let my_tick = me.game.tick() as i64;
+
+        let ghosts = self.racers
+            .iter()
+            .filter(|racer| racer.player.id != player_id)
+            .map(|racer| {
+                let state = racer.game.get_state();
+                let (x, lane) = state.player_pos;
+                // Courses are identical, so a racer's course position is its
+                // tick plus its screen column; shift that into our own frame.
+                let column = racer.game.tick() as i64 + x as i64 - my_tick;
+                Ghost {
+                    name: racer.player.name.clone(),
+                    lane,
+                    column: (0..GAME_WIDTH as i64).contains(&column).then_some(column as usize),
+                    score: state.score,
+                    alive: racer.is_alive(),
+                }
+            })
+            .collect();
+
+        Some(RaceView {
+            room: self.view(),
+            state: me.game.get_state(),
+            ghosts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+    use super::*;
+
+    fn player(name: &str) -> Player {
+        Player { id: name.to_lowercase(), name: name.to_string() }
+    }
+
+    #[test]
+    fn idle_racer_forfeits_so_the_race_can_finish() {
+        let mut room = Room::new("room".to_string(), player("Host"), Arc::new(MemoryStorage::new()));
+        room.join(player("Idle")).unwrap();
+        room.start("host").unwrap();
+
+        room.make_move("host", None).unwrap();
+        room.racers[1].last_move -= RACER_IDLE_TIMEOUT + Duration::from_secs(1);
+        room.make_move("host", Some(PlayerMove::Quit)).unwrap();
+
+        let view = room.view();
+        assert!(view.players[1].forfeited);
+        assert!(!view.players[0].forfeited);
+        assert!(view.status == RoomStatus::Finished);
+        assert!(view.ranking.is_some());
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use warp::{Filter, Rejection};
+use crate::core::PlayerManager;
+use crate::storage::Store;
+
+// Most buckets kept at once. Buckets that have refilled completely carry no
+// state worth keeping and go first; if that isn't enough the least recently
+// used bucket is evicted.
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimitConfig {
+    pub new_games_per_minute: u32,
+    pub moves_per_second: u32,
+    pub max_body_bytes: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            new_games_per_minute: 10,
+            moves_per_second: 20,
+            max_body_bytes: 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimited {
+    pub retry_after: u64,
+}
+impl warp::reject::Reject for RateLimited {}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+// Token bucket per client: each request takes one token, and tokens refill
+// continuously up to `capacity` at `refill_per_sec`.
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32, period: Duration) -> Self {
+        let capacity = capacity.max(1) as f64;
+        Self {
+            capacity,
+            refill_per_sec: capacity / period.as_secs_f64(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Takes a token for `client`, or returns how many seconds to wait.
+    pub fn check(&self, client: &str) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(client) {
+            self.prune(&mut buckets, now);
+            if buckets.len() >= MAX_BUCKETS {
+                evict_least_recent(&mut buckets);
+            }
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            last_refill: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / self.refill_per_sec;
+            Err(wait.ceil().max(1.0) as u64)
+        }
+    }
+
+    fn prune(&self, buckets: &mut HashMap<String, Bucket>, now: Instant) {
+        buckets.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens + elapsed * self.refill_per_sec < self.capacity
+        });
+    }
+}
+
+fn evict_least_recent(buckets: &mut HashMap<String, Bucket>) {
+    let oldest = buckets.iter()
+        .min_by_key(|(_, bucket)| bucket.last_refill)
+        .map(|(client, _)| client.clone());
+    if let Some(client) = oldest {
+        buckets.remove(&client);
+    }
+}
+
+// Clients are identified by their player when they send a valid bearer
+// token, otherwise by remote IP address. Unknown tokens count against the IP
+// so making them up doesn't buy fresh buckets.
+fn client_key(addr: Option<SocketAddr>, authorization: Option<String>, store: &Store) -> String {
+    let player = authorization.as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| PlayerManager::new(store.clone()).authenticate(token.trim()));
+    match player {
+        Some(player) => format!("player:{}", player.id),
+        None => format!("ip:{}", addr.map_or("unknown".to_string(), |a| a.ip().to_string())),
+    }
+}
+
+pub fn rate_limit(
+    limiter: Arc<RateLimiter>,
+    store: Store,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::addr::remote()
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(move |addr: Option<SocketAddr>, authorization: Option<String>| {
+            let limiter = limiter.clone();
+            let store = store.clone();
+            async move {
+                limiter
+                    .check(&client_key(addr, authorization, &store))
+                    .map_err(|retry_after| warp::reject::custom(RateLimited { retry_after }))
+            }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::storage::MemoryStorage;
+    use super::*;
+
+    async fn allowed(filter: &(impl Filter<Extract = (), Error = Rejection> + Clone + 'static), token: &str) -> bool {
+        warp::test::request()
+            .remote_addr("10.0.0.1:4000".parse().unwrap())
+            .header("authorization", format!("Bearer {}", token))
+            .filter(filter)
+            .await
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn made_up_tokens_share_the_ip_bucket() {
+        let store: Store = Arc::new(MemoryStorage::new());
+        let (_, token) = PlayerManager::new(store.clone()).register("Limited").unwrap();
+        let filter = rate_limit(Arc::new(RateLimiter::new(2, Duration::from_secs(60))), store);
+
+        assert!(allowed(&filter, "made-up-1").await);
+        assert!(allowed(&filter, "made-up-2").await);
+        assert!(!allowed(&filter, "made-up-3").await);
+        // A real player has a bucket of their own
+        assert!(allowed(&filter, &token).await);
+    }
+
+    #[test]
+    fn bucket_table_is_capped() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+        for client in 0..MAX_BUCKETS + 10 {
+            let _ = limiter.check(&client.to_string());
+        }
+        assert_eq!(limiter.buckets.lock().unwrap().len(), MAX_BUCKETS);
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::info;
+use crate::core::{Challenge, Game, GameState};
+
+// Spectators that fall further behind than this skip to the latest state
+const UPDATE_CHANNEL_CAPACITY: usize = 16;
+// Sessions with no moves or submissions for this long are dropped from memory
+pub const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(3600);
+
+pub struct GameSession {
+    pub game: Game,
+    pub owner_id: String,
+    pub score_submitted: bool,
+    // Set for daily challenge attempts, whose result is recorded at game over
+    pub challenge: Option<Challenge>,
+    pub updates: broadcast::Sender<GameState>,
+    // Set when the session changes and cleared once it has been snapshotted
+    pub changed: bool,
+    last_active: Instant,
+}
+
+impl GameSession {
+    pub fn new(game: Game, owner_id: String) -> Self {
+        let (updates, _) = broadcast::channel(UPDATE_CHANNEL_CAPACITY);
+        Self {
+            game,
+            owner_id,
+            score_submitted: false,
+            challenge: None,
+            updates,
+            changed: true,
+            last_active: Instant::now(),
+        }
+    }
+
+    // Marks the session for the next snapshot and as still in use
+    pub fn touch(&mut self) {
+        self.changed = true;
+        self.last_active = Instant::now();
+    }
+
+    // Sends the current state to everyone watching; having no watchers is fine.
+    pub fn publish(&self) {
+        let _ = self.updates.send(self.game.get_state());
+    }
+}
+
+pub type Games = Arc<Mutex<HashMap<String, GameSession>>>;
+
+// Drops sessions nobody has used for SESSION_IDLE_TIMEOUT. Their snapshots
+// expire from the store on their own.
+pub fn drop_idle(games: &Games) {
+    games.lock().unwrap().retain(|game_id, session| {
+        let idle = session.last_active.elapsed() > SESSION_IDLE_TIMEOUT;
+        if idle {
+            info!(game_id = %game_id, "dropped idle game session");
+        }
+        !idle
+    });
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use tracing::{debug, warn};
+use crate::core::{Challenge, Game, GameState};
+use crate::server::session::{GameSession, Games, SESSION_IDLE_TIMEOUT};
+use crate::storage::Store;
+
+const SESSION_PREFIX: &str = "session:";
+
+#[derive(Serialize, Deserialize)]
+struct SessionSnapshot {
+    owner_id: String,
+    score_submitted: bool,
+    seed: u64,
+    tick: u32,
+    state: GameState,
+    #[serde(default)]
+    challenge: Option<Challenge>,
+}
+
+// Writes the sessions that changed since the last save. Each write restarts
+// the snapshot's TTL, so a session nobody touches expires from the store.
+// Sessions whose score has already been submitted are finished for good, so
+// their snapshots are removed.
+pub fn save_sessions(store: &Store, games: &Games) {
+    // Serialize under the games lock, then write without holding it
+    let (snapshots, finished): (Vec<_>, Vec<_>) = {
+        let mut games = games.lock().unwrap();
+        games
+            .iter_mut()
+            .filter(|(_, session)| session.changed)
+            .map(|(game_id, session)| {
+                session.changed = false;
+                let snapshot = SessionSnapshot {
+                    owner_id: session.owner_id.clone(),
+                    score_submitted: session.score_submitted,
+                    seed: session.game.seed(),
+                    tick: session.game.tick(),
+                    state: session.game.get_state(),
+                    challenge: session.challenge.clone(),
+                };
+                (format!("{}{}", SESSION_PREFIX, game_id), snapshot)
+            })
+            .partition(|(_, snapshot)| !snapshot.score_submitted)
+    };
+
+    for (key, snapshot) in snapshots {
+        let json = serde_json::to_string(&snapshot).unwrap();
+        if let Err(e) = store.set(&key, &json, Some(SESSION_IDLE_TIMEOUT.as_secs())) {
+            warn!(key = %key, error = %e, "failed to snapshot session");
+        }
+    }
+    for (key, _) in finished {
+        let _ = store.delete(&key);
+    }
+    debug!("session snapshots saved");
+}
+
+pub fn load_sessions(store: &Store) -> HashMap<String, GameSession> {
+    store
+        .scan(SESSION_PREFIX)
+        .into_iter()
+        .filter_map(|entry| {
+            let game_id = entry.key.strip_prefix(SESSION_PREFIX)?.to_string();
+            let snapshot: SessionSnapshot = serde_json::from_str(entry.value.trim_matches('"')).ok()?;
+            Some((game_id, snapshot))
+        })
+        .map(|(game_id, snapshot)| {
+            let game = Game::restore(store.clone(), snapshot.seed, snapshot.tick, snapshot.state);
+            let mut session = GameSession::new(game, snapshot.owner_id);
+            session.score_submitted = snapshot.score_submitted;
+            session.challenge = snapshot.challenge;
+            // Already saved as it is; rewriting it would extend its TTL
+            session.changed = false;
+            (game_id, session)
+        })
+        .collect()
+}
@@ -0,0 +1,74 @@
+use std::sync::Mutex;
+use simple_kv_store::KvStore;
+use super::{Entry, Storage, StorageError};
+
+// The simple_kv_store crate behind a single lock
+pub struct KvStorage {
+    store: Mutex<KvStore>,
+}
+
+impl KvStorage {
+    pub fn new() -> Result<Self, StorageError> {
+        let store = KvStore::new().map_err(|e| StorageError(e.to_string()))?;
+        Ok(Self { store: Mutex::new(store) })
+    }
+}
+
+impl Storage for KvStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        self.store.lock().unwrap().get(key).map(|value| value.to_string())
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<u64>) -> Result<(), StorageError> {
+        self.store.lock().unwrap()
+            .set_with_ttl(key.to_string(), value.to_string(), ttl)
+            .map(|_| ())
+            .map_err(|e| StorageError(e.to_string()))
+    }
+
+    fn insert_new(&self, key: &str, value: &str, ttl: Option<u64>) -> Result<bool, StorageError> {
+        let mut store = self.store.lock().unwrap();
+        if store.get(key).is_some() {
+            return Ok(false);
+        }
+        store.set_with_ttl(key.to_string(), value.to_string(), ttl)
+            .map(|_| true)
+            .map_err(|e| StorageError(e.to_string()))
+    }
+
+    fn delete(&self, key: &str) -> Result<Option<String>, StorageError> {
+        self.store.lock().unwrap()
+            .delete(key)
+            .map(|value| value.map(|value| value.to_string()))
+            .map_err(|e| StorageError(e.to_string()))
+    }
+
+    fn scan(&self, prefix: &str) -> Vec<Entry> {
+        self.store.lock().unwrap()
+            .get_all()
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| Entry {
+                key: key.clone(),
+                value: value.data.to_string(),
+                expires_at: value.expires_at,
+            })
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.store.lock().unwrap().len()
+    }
+
+    fn is_healthy(&self) -> bool {
+        !self.store.is_poisoned()
+    }
+
+    // simple_kv_store has nothing to flush, so this is a no-op apart from
+    // reporting a poisoned lock, which means a write was cut short.
+    fn flush(&self) -> Result<(), StorageError> {
+        self.store.lock()
+            .map(|_| ())
+            .map_err(|_| StorageError("store lock poisoned; last write may be incomplete".to_string()))
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use super::{now, validate, Entry, Storage, StorageError};
+
+struct Slot {
+    value: String,
+    expires_at: Option<u64>,
+}
+
+impl Slot {
+    fn is_live(&self, now: u64) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+// Keeps everything in process memory; nothing survives a restart. Used for
+// throwaway runs and tests. Expired keys are dropped lazily on access.
+#[derive(Default)]
+pub struct MemoryStorage {
+    entries: Mutex<HashMap<String, Slot>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(slot) if slot.is_live(now()) => Some(slot.value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn set(&self, key: &str, value: &str, ttl: Option<u64>) -> Result<(), StorageError> {
+        validate(value)?;
+        let slot = Slot { value: value.to_string(), expires_at: ttl.map(|ttl| now() + ttl) };
+        self.entries.lock().unwrap().insert(key.to_string(), slot);
+        Ok(())
+    }
+
+    fn insert_new(&self, key: &str, value: &str, ttl: Option<u64>) -> Result<bool, StorageError> {
+        validate(value)?;
+        let now = now();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.get(key).is_some_and(|slot| slot.is_live(now)) {
+            return Ok(false);
+        }
+        let slot = Slot { value: value.to_string(), expires_at: ttl.map(|ttl| now + ttl) };
+        entries.insert(key.to_string(), slot);
+        Ok(true)
+    }
+
+    fn delete(&self, key: &str) -> Result<Option<String>, StorageError> {
+        let slot = self.entries.lock().unwrap().remove(key);
+        Ok(slot.filter(|slot| slot.is_live(now())).map(|slot| slot.value))
+    }
+
+    fn scan(&self, prefix: &str) -> Vec<Entry> {
+        let now = now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, slot| slot.is_live(now));
+        entries
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .map(|(key, slot)| Entry {
+                key: key.clone(),
+                value: slot.value.clone(),
+                expires_at: slot.expires_at,
+            })
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        let now = now();
+        self.entries.lock().unwrap().values().filter(|slot| slot.is_live(now)).count()
+    }
+}
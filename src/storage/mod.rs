@@ -0,0 +1,223 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::SystemTime;
+
+mod kv;
+mod memory;
+
+pub use kv::KvStorage;
+pub use memory::MemoryStorage;
+
+// Shared handle to whichever backend the binary was started with
+pub type Store = Arc<dyn Storage>;
+
+const KEY_LOCKS: usize = 64;
+static KEY_LOCK_TABLE: [Mutex<()>; KEY_LOCKS] = [const { Mutex::new(()) }; KEY_LOCKS];
+
+#[derive(Debug)]
+pub struct StorageError(pub String);
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        StorageError(e.to_string())
+    }
+}
+
+pub struct Entry {
+    pub key: String,
+    // JSON text of the stored value
+    pub value: String,
+    // Unix timestamp in seconds
+    pub expires_at: Option<u64>,
+}
+
+// Key-value storage used by the game, the admin CLI and the server. Values are
+// JSON text and TTLs are in seconds. Implementations do their own locking, so
+// a `Store` can be shared between threads without an outer mutex.
+pub trait Storage: Send + Sync {
+    fn get(&self, key: &str) -> Option<String>;
+
+    fn set(&self, key: &str, value: &str, ttl: Option<u64>) -> Result<(), StorageError>;
+
+    // Writes the key only if it doesn't exist yet, as one atomic step.
+    // Returns false if the key was already taken.
+    fn insert_new(&self, key: &str, value: &str, ttl: Option<u64>) -> Result<bool, StorageError>;
+
+    // Returns the removed value, if there was one
+    fn delete(&self, key: &str) -> Result<Option<String>, StorageError>;
+
+    // Every entry whose key starts with `prefix`, in no particular order
+    fn scan(&self, prefix: &str) -> Vec<Entry>;
+
+    fn len(&self) -> usize;
+
+    // The `limit` entries under `prefix` with the highest numeric `field` in
+    // their JSON value, best first. Ties go to the entry with the earlier
+    // `timestamp` field, then the lower key. Backends with an index
+    // override this.
+    fn top_n(&self, prefix: &str, field: &str, limit: usize) -> Vec<Entry> {
+        scan_top_n(self.scan(prefix), field, limit)
+    }
+
+    // Entries under `prefix` whose JSON value has `field` equal to `value`
+    fn find_by(&self, prefix: &str, field: &str, value: &str) -> Vec<Entry> {
+        scan_find_by(self.scan(prefix), field, value)
+    }
+
+    // False once the backend can no longer serve requests
+    fn is_healthy(&self) -> bool {
+        true
+    }
+
+    // Makes every completed write durable
+    fn flush(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
+
+fn field(entry: &Entry, field: &str) -> Option<serde_json::Value> {
+    let value: serde_json::Value = serde_json::from_str(entry.value.trim_matches('"')).ok()?;
+    value.get(field).cloned()
+}
+
+pub fn scan_top_n(entries: Vec<Entry>, name: &str, limit: usize) -> Vec<Entry> {
+    let mut ranked: Vec<(f64, Option<u64>, Entry)> = entries
+        .into_iter()
+        .filter_map(|entry| {
+            let value = field(&entry, name)?.as_f64()?;
+            let timestamp = field(&entry, "timestamp").and_then(|timestamp| timestamp.as_u64());
+            Some((value, timestamp, entry))
+        })
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.0.total_cmp(&a.0)
+            .then_with(|| a.1.cmp(&b.1))
+            .then_with(|| a.2.key.cmp(&b.2.key))
+    });
+    ranked.into_iter().take(limit).map(|(_, _, entry)| entry).collect()
+}
+
+pub fn scan_find_by(entries: Vec<Entry>, name: &str, value: &str) -> Vec<Entry> {
+    entries
+        .into_iter()
+        .filter(|entry| field(entry, name).is_some_and(|field| field.as_str() == Some(value)))
+        .collect()
+}
+
+// Values must be JSON, matching what KvStore accepts
+pub fn validate(value: &str) -> Result<(), StorageError> {
+    serde_json::from_str::<serde_json::Value>(value)
+        .map(|_| ())
+        .map_err(|e| StorageError(format!("value is not valid JSON: {}", e)))
+}
+
+// Held across a get-modify-set of `key` so concurrent updates in this process
+// don't overwrite each other. Keys share a fixed table of locks, so never
+// take a second one while holding this.
+pub fn lock_key(key: &str) -> MutexGuard<'static, ()> {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    KEY_LOCK_TABLE[hasher.finish() as usize % KEY_LOCKS]
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+// The behaviour every backend must share, run against each one
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+    use crate::persistence::file::FileStorage;
+    use super::*;
+
+    fn contract(store: &dyn Storage) {
+        // Keys are namespaced so backends with existing data aren't disturbed
+        let prefix = format!("contract-{}:", Uuid::new_v4().simple());
+        let key = |name: &str| format!("{}{}", prefix, name);
+        let len = store.len();
+
+        assert_eq!(store.get(&key("a")), None);
+        store.set(&key("a"), r#"{"score":1}"#, None).unwrap();
+        assert_eq!(store.get(&key("a")).as_deref(), Some(r#"{"score":1}"#));
+        store.set(&key("a"), r#"{"score":2}"#, None).unwrap();
+        assert_eq!(store.get(&key("a")).as_deref(), Some(r#"{"score":2}"#));
+        assert!(store.set(&key("bad"), "not json", None).is_err());
+
+        assert!(store.insert_new(&key("b"), r#"{"score":3}"#, None).unwrap());
+        assert!(!store.insert_new(&key("b"), r#"{"score":4}"#, None).unwrap());
+        assert_eq!(store.get(&key("b")).as_deref(), Some(r#"{"score":3}"#));
+
+        store.set(&key("c"), r#"{"score":5,"timestamp":20}"#, Some(60)).unwrap();
+        let expires_at = store.scan(&key("c")).pop().unwrap().expires_at.unwrap();
+        assert!(expires_at > now() && expires_at <= now() + 60);
+
+        store.set(&key("d"), r#"{"score":5,"timestamp":10}"#, None).unwrap();
+
+        let mut keys: Vec<String> = store.scan(&prefix).into_iter().map(|entry| entry.key).collect();
+        keys.sort();
+        assert_eq!(keys, vec![key("a"), key("b"), key("c"), key("d")]);
+        assert_eq!(store.len(), len + 4);
+
+        // Equal scores go to the earlier timestamp
+        let top: Vec<String> = store.top_n(&prefix, "score", 3).into_iter().map(|entry| entry.key).collect();
+        assert_eq!(top, vec![key("d"), key("c"), key("b")]);
+
+        assert_eq!(store.delete(&key("a")).unwrap().as_deref(), Some(r#"{"score":2}"#));
+        assert_eq!(store.delete(&key("a")).unwrap(), None);
+        for name in ["b", "c", "d"] {
+            store.delete(&key(name)).unwrap();
+        }
+        assert_eq!(store.len(), len);
+
+        assert!(store.is_healthy());
+        store.flush().unwrap();
+    }
+
+    fn temp_path(extension: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("side_scroller-{}.{}", Uuid::new_v4().simple(), extension))
+    }
+
+    #[test]
+    fn memory_storage_meets_the_contract() {
+        contract(&MemoryStorage::new());
+    }
+
+    #[test]
+    fn file_storage_meets_the_contract() {
+        let path = temp_path("log");
+        contract(&FileStorage::open(&path).unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // simple_kv_store can only open its default store, which holds real
+    // data, so this runs only when asked for with --ignored
+    #[test]
+    #[ignore = "writes to the default KV store"]
+    fn kv_storage_meets_the_contract() {
+        contract(&KvStorage::new().unwrap());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_storage_meets_the_contract() {
+        let path = temp_path("db");
+        contract(&crate::persistence::sqlite::SqliteStorage::open(&path).unwrap());
+        let _ = std::fs::remove_file(path);
+    }
+}
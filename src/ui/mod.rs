@@ -1,5 +1,7 @@
 mod renderer;
 mod input;
+mod profile;
 
-pub use renderer::render_game;
-pub use input::{handle_input, ask_play_again}; 
\ No newline at end of file
+pub use renderer::{render_game, render_game_with_ghosts, render_score_breakdown};
+pub use input::{handle_input, ask_play_again};
+pub use profile::{announce_achievements, render_profile};
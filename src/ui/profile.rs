@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+use crate::core::{format_date, ticks_to_secs, Achievement, Profile, ACHIEVEMENTS};
+
+const WIDTH: usize = 40;
+
+// `earned` maps achievement ids to the time they were earned
+pub fn render_profile(name: &str, profile: &Profile, earned: &BTreeMap<String, u64>) {
+    println!("+{}+", "-".repeat(WIDTH));
+    println!("|{:^width$}|", name, width = WIDTH);
+    println!("+{}+", "-".repeat(WIDTH));
+
+    if profile.games_played == 0 {
+        println!("|{:^width$}|", "No games finished yet", width = WIDTH);
+        println!("+{}+", "-".repeat(WIDTH));
+        return;
+    }
+
+    row("Games played", profile.games_played.to_string());
+    row("Total score", profile.total_score.to_string());
+    row("Average score", format!("{:.1}", profile.average_score()));
+    if let Some(best) = &profile.best_run {
+        row("Best run", format!("{} ({})", best.score, format_date(best.recorded_at)));
+        row("  survived", format!("{:.1}s", ticks_to_secs(best.ticks)));
+        if let Some(replay_id) = &best.replay_id {
+            row("  replay", replay_id.clone());
+        }
+    }
+    row("Longest survival", format!("{:.1}s", profile.longest_survival_secs()));
+    row("Collisions (top)", profile.collisions[0].to_string());
+    row("Collisions (bottom)", profile.collisions[1].to_string());
+    row("Power-ups collected", profile.pickups_collected.to_string());
+    println!("+{}+", "-".repeat(WIDTH));
+
+    println!("Achievements ({}/{}):", earned.len(), ACHIEVEMENTS.len());
+    for achievement in ACHIEVEMENTS {
+        match earned.get(achievement.id) {
+            Some(&at) => println!("  [x] {} - {} ({})", achievement.name, achievement.description, format_date(at)),
+            None => println!("  [ ] {} - {}", achievement.name, achievement.description),
+        }
+    }
+}
+
+pub fn announce_achievements(achievements: &[&Achievement]) {
+    for achievement in achievements {
+        println!("Achievement unlocked: {} - {}", achievement.name, achievement.description);
+    }
+}
+
+fn row(label: &str, value: String) {
+    println!("| {:<20}{:>width$} |", label, value, width = WIDTH - 22);
+}
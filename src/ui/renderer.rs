@@ -4,36 +4,51 @@ use crossterm::{
     terminal::{Clear, ClearType},
     cursor::MoveTo,
 };
-use crate::core::GameState;
+use crate::core::{Achievement, GameState, ScoreBreakdown};
 
 pub fn render_game(state: &GameState) {
+    render_game_with_ghosts(state, &[]);
+}
+
+// Ghosts are other racers' (column, lane) positions, drawn as `+`.
+pub fn render_game_with_ghosts(state: &GameState, ghosts: &[(usize, usize)]) {
     execute!(io::stdout(), Clear(ClearType::All), MoveTo(0, 0)).unwrap();
     
-    println!("Score: {}", state.score);
+    let combo = if state.combo.count > 1 { format!("  Combo x{}", state.combo.multiplier()) } else { String::new() };
+    let unlocked = state.achievements
+        .last()
+        .and_then(|id| Achievement::by_id(id))
+        .map_or(String::new(), |achievement| format!("  Unlocked: {}", achievement.name));
+    println!("Score: {}  Pickups: {}{}{}", state.score, state.pickups, combo, unlocked);
     
     execute!(io::stdout(), MoveTo(0, 1)).unwrap();
-    
-    for (i, &has_obstacle) in state.top_row.iter().enumerate() {
-        if state.player_pos == (i, 0) {
-            print!("x");
-        } else if has_obstacle {
-            print!("-");
-        } else {
-            print!(" ");
-        }
-    }
+    render_row(state, &state.top_row, &state.top_pickups, 0, ghosts);
     
     execute!(io::stdout(), MoveTo(0, 2)).unwrap();
+    render_row(state, &state.bottom_row, &state.bottom_pickups, 1, ghosts);
     
-    for (i, &has_obstacle) in state.bottom_row.iter().enumerate() {
-        if state.player_pos == (i, 1) {
+    io::stdout().flush().unwrap();
+}
+
+fn render_row(state: &GameState, row: &[bool], pickups: &[bool], lane: usize, ghosts: &[(usize, usize)]) {
+    for (i, &has_obstacle) in row.iter().enumerate() {
+        if state.player_pos == (i, lane) {
             print!("x");
         } else if has_obstacle {
             print!("-");
+        } else if pickups.get(i).copied().unwrap_or(false) {
+            print!("o");
+        } else if ghosts.contains(&(i, lane)) {
+            print!("+");
         } else {
             print!(" ");
         }
     }
-    
-    io::stdout().flush().unwrap();
-} 
\ No newline at end of file
+}
+
+pub fn render_score_breakdown(breakdown: &ScoreBreakdown) {
+    println!("\nGame Over! Final score: {}", breakdown.total());
+    println!("  Distance:     {}", breakdown.distance);
+    println!("  Near misses:  {} (+{})", breakdown.near_misses, breakdown.bonus);
+    println!("  Best combo:   {}", breakdown.combo_peak);
+}